@@ -0,0 +1,1160 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{Instruction, PROGRAM_START};
+
+/// A program assembled from Octo source.
+pub struct Assembly {
+    /// The program bytes, to be loaded at [`PROGRAM_START`].
+    pub program: Vec<u8>,
+    /// Every label and the address it points to.
+    pub symbols: BTreeMap<String, u16>,
+}
+
+impl Assembly {
+    /// Lists every symbol as `0xADDR name`, one per line, ordered by address.
+    pub fn symbol_map(&self) -> String {
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort_by_key(|(name, address)| (**address, name.as_str()));
+        symbols
+            .into_iter()
+            .map(|(name, address)| format!("0x{address:04X} {name}\n"))
+            .collect()
+    }
+}
+
+/// Why a program couldn't be assembled, with the source line it happened on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+type AssemblyResult<T> = Result<T, AssemblyError>;
+
+/// Assembles a program written in Octo syntax.
+///
+/// Supported are labels, `:alias`, `:const`, `:calc`, `:macro`, `:org`, `:byte`, `:pointer`,
+/// `:call`, `:unpack`, `loop`/`while`/`again`, `if ... then`, `if ... begin`/`else`/`end`,
+/// byte literals and every CHIP-8, SUPER-CHIP and XO-CHIP statement. Like Octo, the program
+/// starts with a jump to the `main` label, unless `main` is the first thing in the program.
+pub fn assemble(source: &str) -> Result<Assembly, AssemblyError> {
+    let mut assembler = Assembler::new(tokenize(source));
+    while assembler.position < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+    assembler.finish()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    /// Number of nested macro expansions the token came from.
+    depth: usize,
+}
+
+/// Deepest macro nesting allowed, to catch macros that expand themselves.
+const MAX_MACRO_DEPTH: usize = 64;
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        let code = code
+            .replace('{', " { ")
+            .replace('}', " } ")
+            .replace('(', " ( ")
+            .replace(')', " ) ");
+        for text in code.split_whitespace() {
+            tokens.push(Token {
+                text: text.to_string(),
+                line: index + 1,
+                depth: 0,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse::<f64>().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+/// A condition of an `if` or `while`.
+#[derive(Clone, Copy)]
+enum Condition {
+    Equal(u8, Operand),
+    NotEqual(u8, Operand),
+    Less(u8, Operand),
+    GreaterOrEqual(u8, Operand),
+    Greater(u8, Operand),
+    LessOrEqual(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::Equal(x, rhs) => Condition::NotEqual(x, rhs),
+            Condition::NotEqual(x, rhs) => Condition::Equal(x, rhs),
+            Condition::Less(x, rhs) => Condition::GreaterOrEqual(x, rhs),
+            Condition::GreaterOrEqual(x, rhs) => Condition::Less(x, rhs),
+            Condition::Greater(x, rhs) => Condition::LessOrEqual(x, rhs),
+            Condition::LessOrEqual(x, rhs) => Condition::Greater(x, rhs),
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Value(u8),
+}
+
+/// Where a label's address has to be patched in once it is defined.
+#[derive(Clone, Copy)]
+enum FixupKind {
+    /// The NNN part of a 2-byte instruction.
+    Address,
+    /// A full 16-bit address, as used by `i := long` and `:pointer`.
+    Long,
+    /// The high nibble of an `:unpack`, in the low nibble of a `v0 :=` instruction.
+    UnpackHigh,
+    /// The low byte of an `:unpack`, in a `v1 :=` instruction.
+    UnpackLow,
+}
+
+struct Fixup {
+    /// Offset in the program of the bytes to patch: the instruction for [`FixupKind::Address`]
+    /// and the unpacks, or the 16-bit address itself for [`FixupKind::Long`].
+    offset: usize,
+    kind: FixupKind,
+    label: String,
+    line: usize,
+}
+
+enum Block {
+    Loop { start: u16, breaks: Vec<usize> },
+    If { jump: usize },
+    Else { jump: usize },
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    position: usize,
+    program: Vec<u8>,
+    here: u16,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    /// Whether the first two bytes are reserved for a jump to `main`.
+    main_jump: bool,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            program: vec![0; 2],
+            here: PROGRAM_START + 2,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            main_jump: true,
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position.saturating_sub(1))
+            .map_or(0, |token| token.line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> AssemblyResult<T> {
+        Err(AssemblyError {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn next(&mut self) -> AssemblyResult<String> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.text.clone())
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> AssemblyResult<()> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected '{expected}' but found '{token}'"));
+        }
+        Ok(())
+    }
+
+    /// Position of `here` in the program.
+    fn offset(&self) -> AssemblyResult<usize> {
+        match self.here.checked_sub(PROGRAM_START) {
+            Some(offset) => Ok(offset as usize),
+            None => self.error("cannot emit code below 0x200"),
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> AssemblyResult<()> {
+        let offset = self.offset()?;
+        if offset >= self.program.len() {
+            self.program.resize(offset + 1, 0);
+        }
+        self.program[offset] = byte;
+        self.here = match self.here.checked_add(1) {
+            Some(here) => here,
+            None => return self.error("program does not fit in 64 KiB"),
+        };
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> AssemblyResult<()> {
+        for byte in instruction.to_bytes() {
+            self.emit_byte(byte)?;
+        }
+        Ok(())
+    }
+
+    /// Emits an instruction taking an address, which is patched in later if `target` is a
+    /// label that isn't defined yet.
+    fn emit_with_address(
+        &mut self,
+        target: &str,
+        kind: FixupKind,
+        instruction: impl Fn(u16) -> Instruction,
+    ) -> AssemblyResult<()> {
+        let address = match self.lookup(target) {
+            Some(value) => self.address(value, kind)?,
+            None if is_identifier(target) => {
+                let offset = match kind {
+                    // The address follows the F000 opcode of `i := long`.
+                    FixupKind::Long => self.offset()? + 2,
+                    _ => self.offset()?,
+                };
+                self.fixups.push(Fixup {
+                    offset,
+                    kind,
+                    label: target.to_string(),
+                    line: self.line(),
+                });
+                0
+            }
+            None => return self.error(format!("expected an address but found '{target}'")),
+        };
+        self.emit(instruction(address))
+    }
+
+    fn address(&self, value: f64, kind: FixupKind) -> AssemblyResult<u16> {
+        let max = match kind {
+            FixupKind::Address => 0xFFF,
+            _ => 0xFFFF,
+        };
+        let address = value.floor() as i64;
+        if !(0..=max).contains(&address) {
+            return self.error(format!("address 0x{address:X} is out of range"));
+        }
+        Ok(address as u16)
+    }
+
+    fn lookup(&self, name: &str) -> Option<f64> {
+        if let Some(value) = parse_number(name) {
+            return Some(value);
+        }
+        self.constants
+            .get(name)
+            .copied()
+            .or_else(|| self.labels.get(name).map(|address| *address as f64))
+    }
+
+    fn register(&mut self) -> AssemblyResult<u8> {
+        let token = self.next()?;
+        match self.try_register(&token) {
+            Some(register) => Ok(register),
+            None => self.error(format!("expected a register but found '{token}'")),
+        }
+    }
+
+    fn try_register(&self, token: &str) -> Option<u8> {
+        parse_register(token).or_else(|| self.aliases.get(token).copied())
+    }
+
+    /// Reads a number, constant or `{ calc }` expression.
+    fn value(&mut self) -> AssemblyResult<f64> {
+        let token = self.next()?;
+        if token == "{" {
+            return self.calc();
+        }
+        match self.lookup(&token) {
+            Some(value) => Ok(value),
+            None => self.error(format!("expected a value but found '{token}'")),
+        }
+    }
+
+    fn byte(&mut self) -> AssemblyResult<u8> {
+        let value = self.value()?.floor() as i64;
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("{value} does not fit in a byte"));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> AssemblyResult<u8> {
+        let value = self.value()?.floor() as i64;
+        if !(0..=15).contains(&value) {
+            return self.error(format!("{value} does not fit in a nibble"));
+        }
+        Ok(value as u8)
+    }
+
+    fn operand(&mut self) -> AssemblyResult<Operand> {
+        match self.peek().and_then(|token| self.try_register(token)) {
+            Some(register) => {
+                self.position += 1;
+                Ok(Operand::Register(register))
+            }
+            None => Ok(Operand::Value(self.byte()?)),
+        }
+    }
+
+    fn statement(&mut self) -> AssemblyResult<()> {
+        let token = self.next()?;
+        if let Some(register) = self.try_register(&token) {
+            return self.register_statement(register);
+        }
+        match token.as_str() {
+            ":" => self.label(),
+            ":alias" => {
+                let name = self.next()?;
+                let register = if self.peek() == Some("{") {
+                    self.value()? as u8
+                } else {
+                    self.register()?
+                };
+                if register > 0xF {
+                    return self.error(format!("v{register:x} is not a register"));
+                }
+                self.aliases.insert(name, register);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":org" => {
+                let address = self.value()?;
+                self.here = self.address(address, FixupKind::Long)?;
+                Ok(())
+            }
+            ":macro" => self.define_macro(),
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte)
+            }
+            ":pointer" => {
+                let target = self.next()?;
+                let offset = self.offset()?;
+                self.emit_byte(0)?;
+                self.emit_byte(0)?;
+                self.patch_or_fixup(&target, offset, FixupKind::Long)
+            }
+            ":call" => {
+                let target = self.next()?;
+                self.emit_with_address(&target, FixupKind::Address, |address| Instruction::Call {
+                    address,
+                })
+            }
+            ":unpack" => {
+                let high = self.nibble()?;
+                let target = self.next()?;
+                let offset = self.offset()?;
+                self.emit(Instruction::SetImmediate {
+                    x: 0x0,
+                    value: high << 4,
+                })?;
+                self.emit(Instruction::SetImmediate { x: 0x1, value: 0 })?;
+                self.patch_or_fixup(&target, offset, FixupKind::UnpackHigh)?;
+                self.patch_or_fixup(&target, offset + 2, FixupKind::UnpackLow)
+            }
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            }
+            "clear" => self.emit(Instruction::Clear),
+            "return" | ";" => self.emit(Instruction::Return),
+            "hires" => self.emit(Instruction::HighResolution),
+            "lores" => self.emit(Instruction::LowResolution),
+            "exit" => self.emit(Instruction::Exit),
+            "scroll-down" => {
+                let rows = self.nibble()?;
+                self.emit(Instruction::ScrollDown { rows })
+            }
+            "scroll-up" => {
+                let rows = self.nibble()?;
+                self.emit(Instruction::ScrollUp { rows })
+            }
+            "scroll-left" => self.emit(Instruction::ScrollLeft),
+            "scroll-right" => self.emit(Instruction::ScrollRight),
+            "audio" => self.emit(Instruction::LoadAudioPattern),
+            "plane" => {
+                let planes = self.nibble()?;
+                if planes > 3 {
+                    return self.error("plane must be between 0 and 3");
+                }
+                self.emit(Instruction::SelectPlanes { planes })
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::Bcd { x })
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let is_range = self.peek() == Some("-")
+                    && self
+                        .tokens
+                        .get(self.position + 1)
+                        .is_some_and(|token| self.try_register(&token.text).is_some());
+                let instruction = if is_range {
+                    self.position += 1;
+                    let y = self.register()?;
+                    if token == "save" {
+                        Instruction::StoreRange { x, y }
+                    } else {
+                        Instruction::LoadRange { x, y }
+                    }
+                } else if token == "save" {
+                    Instruction::Store { x }
+                } else {
+                    Instruction::Load { x }
+                };
+                self.emit(instruction)
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::StoreFlags { x })
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags { x })
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.nibble()?;
+                self.emit(Instruction::Draw { x, y, height })
+            }
+            "jump" => {
+                let target = self.next()?;
+                self.emit_with_address(&target, FixupKind::Address, |address| Instruction::Jump {
+                    address,
+                })
+            }
+            "jump0" => {
+                let target = self.next()?;
+                self.emit_with_address(&target, FixupKind::Address, |address| {
+                    Instruction::JumpOffset { address }
+                })
+            }
+            "native" => {
+                let target = self.next()?;
+                // 0NNN machine code calls have no instruction of their own, so reuse the
+                // address encoding of `jump` and clear the opcode nibble.
+                let offset = self.offset()?;
+                self.emit_with_address(&target, FixupKind::Address, |address| Instruction::Jump {
+                    address,
+                })?;
+                self.program[offset] &= 0x0F;
+                Ok(())
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.as_str() {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::SetPitch { x },
+                })
+            }
+            "i" => self.index_statement(),
+            "if" => self.if_statement(),
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let offset = self.offset()?;
+                    self.emit(Instruction::Jump { address: 0 })?;
+                    self.patch_jump(jump, self.here);
+                    self.blocks.push(Block::Else { jump: offset });
+                    Ok(())
+                }
+                _ => self.error("'else' without 'if ... begin'"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump } | Block::Else { jump }) => {
+                    self.patch_jump(jump, self.here);
+                    Ok(())
+                }
+                _ => self.error("'end' without 'if ... begin'"),
+            },
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    start: self.here,
+                    breaks: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.skip_unless(condition.negate())?;
+                let offset = self.offset()?;
+                self.emit(Instruction::Jump { address: 0 })?;
+                match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { breaks, .. } => Some(breaks),
+                    _ => None,
+                }) {
+                    Some(breaks) => {
+                        breaks.push(offset);
+                        Ok(())
+                    }
+                    None => self.error("'while' outside of 'loop'"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks }) => {
+                    self.emit(Instruction::Jump { address: start })?;
+                    for offset in breaks {
+                        self.patch_jump(offset, self.here);
+                    }
+                    Ok(())
+                }
+                _ => self.error("'again' without 'loop'"),
+            },
+            "{" => {
+                let value = self.calc()?;
+                self.emit_value_byte(value)
+            }
+            _ => {
+                if let Some(value) = parse_number(&token) {
+                    self.emit_value_byte(value)
+                } else if let Some(value) = self.constants.get(&token).copied() {
+                    self.emit_value_byte(value)
+                } else if self.macros.contains_key(&token) {
+                    self.expand_macro(&token)
+                } else if is_identifier(&token) {
+                    self.emit_with_address(&token, FixupKind::Address, |address| {
+                        Instruction::Call { address }
+                    })
+                } else {
+                    self.error(format!("unexpected '{token}'"))
+                }
+            }
+        }
+    }
+
+    fn emit_value_byte(&mut self, value: f64) -> AssemblyResult<()> {
+        let value = value.floor() as i64;
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("{value} does not fit in a byte"));
+        }
+        self.emit_byte(value as u8)
+    }
+
+    fn label(&mut self) -> AssemblyResult<()> {
+        let name = self.next()?;
+        if !is_identifier(&name) {
+            return self.error(format!("'{name}' is not a valid label name"));
+        }
+        if self.labels.contains_key(&name) {
+            return self.error(format!("label '{name}' is defined twice"));
+        }
+        if name == "main"
+            && self.main_jump
+            && self.here == PROGRAM_START + 2
+            && self.program.len() == 2
+            && self.labels.is_empty()
+        {
+            // main comes first, so there's no need to jump to it.
+            self.program.clear();
+            self.here = PROGRAM_START;
+            self.main_jump = false;
+        }
+        self.labels.insert(name, self.here);
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> AssemblyResult<()> {
+        let operator = self.next()?;
+        let instruction = match operator.as_str() {
+            ":=" => match self.peek() {
+                Some("random") => {
+                    self.position += 1;
+                    let value = self.byte()?;
+                    Instruction::Random { x, value }
+                }
+                Some("key") => {
+                    self.position += 1;
+                    Instruction::WaitKey { x }
+                }
+                Some("delay") => {
+                    self.position += 1;
+                    Instruction::GetDelay { x }
+                }
+                _ => match self.operand()? {
+                    Operand::Register(y) => Instruction::Set { x, y },
+                    Operand::Value(value) => Instruction::SetImmediate { x, value },
+                },
+            },
+            "+=" => match self.operand()? {
+                Operand::Register(y) => Instruction::Add { x, y },
+                Operand::Value(value) => Instruction::AddImmediate { x, value },
+            },
+            "-=" => match self.operand()? {
+                Operand::Register(y) => Instruction::Subtract { x, y },
+                Operand::Value(value) => Instruction::AddImmediate {
+                    x,
+                    value: value.wrapping_neg(),
+                },
+            },
+            "|=" | "&=" | "^=" | "=-" | ">>=" | "<<=" => {
+                let y = self.register()?;
+                match operator.as_str() {
+                    "|=" => Instruction::Or { x, y },
+                    "&=" => Instruction::And { x, y },
+                    "^=" => Instruction::Xor { x, y },
+                    "=-" => Instruction::SubtractReversed { x, y },
+                    ">>=" => Instruction::ShiftRight { x, y },
+                    _ => Instruction::ShiftLeft { x, y },
+                }
+            }
+            _ => return self.error(format!("unknown register operation '{operator}'")),
+        };
+        self.emit(instruction)
+    }
+
+    fn index_statement(&mut self) -> AssemblyResult<()> {
+        let operator = self.next()?;
+        match operator.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddIndex { x })
+            }
+            ":=" => match self.next()?.as_str() {
+                "hex" => {
+                    let x = self.register()?;
+                    self.emit(Instruction::Font { x })
+                }
+                "bighex" => {
+                    let x = self.register()?;
+                    self.emit(Instruction::BigFont { x })
+                }
+                "long" => {
+                    let target = self.next()?;
+                    self.emit_with_address(&target, FixupKind::Long, |address| {
+                        Instruction::SetIndexLong { address }
+                    })
+                }
+                "{" => {
+                    let value = self.calc()?;
+                    let address = self.address(value, FixupKind::Address)?;
+                    self.emit(Instruction::SetIndex { address })
+                }
+                target => {
+                    let target = target.to_string();
+                    self.emit_with_address(&target, FixupKind::Address, |address| {
+                        Instruction::SetIndex { address }
+                    })
+                }
+            },
+            _ => self.error(format!("unknown index operation '{operator}'")),
+        }
+    }
+
+    fn condition(&mut self) -> AssemblyResult<Condition> {
+        let x = self.register()?;
+        let operator = self.next()?;
+        let condition = match operator.as_str() {
+            "key" => Condition::Key(x),
+            "-key" => Condition::NotKey(x),
+            "==" => Condition::Equal(x, self.operand()?),
+            "!=" => Condition::NotEqual(x, self.operand()?),
+            "<" => Condition::Less(x, self.operand()?),
+            ">=" => Condition::GreaterOrEqual(x, self.operand()?),
+            ">" => Condition::Greater(x, self.operand()?),
+            "<=" => Condition::LessOrEqual(x, self.operand()?),
+            _ => return self.error(format!("unknown comparison '{operator}'")),
+        };
+        Ok(condition)
+    }
+
+    fn if_statement(&mut self) -> AssemblyResult<()> {
+        let condition = self.condition()?;
+        match self.next()?.as_str() {
+            "then" => self.skip_unless(condition),
+            "begin" => {
+                self.skip_unless(condition.negate())?;
+                let offset = self.offset()?;
+                self.emit(Instruction::Jump { address: 0 })?;
+                self.blocks.push(Block::If { jump: offset });
+                Ok(())
+            }
+            other => self.error(format!("expected 'then' or 'begin' but found '{other}'")),
+        }
+    }
+
+    /// Emits code after which the next instruction is only executed if `condition` holds.
+    fn skip_unless(&mut self, condition: Condition) -> AssemblyResult<()> {
+        const VF: u8 = 0xF;
+        // Comparisons are done by subtracting in VF and checking the borrow flag.
+        let compare = |this: &mut Self, x: u8, rhs: Operand, reversed: bool, flag: u8| {
+            this.emit(match rhs {
+                Operand::Register(y) => Instruction::Set { x: VF, y },
+                Operand::Value(value) => Instruction::SetImmediate { x: VF, value },
+            })?;
+            this.emit(if reversed {
+                Instruction::SubtractReversed { x: VF, y: x }
+            } else {
+                Instruction::Subtract { x: VF, y: x }
+            })?;
+            this.emit(Instruction::SkipIfNotEqual { x: VF, value: flag })
+        };
+        match condition {
+            Condition::Equal(x, Operand::Value(value)) => {
+                self.emit(Instruction::SkipIfNotEqual { x, value })
+            }
+            Condition::Equal(x, Operand::Register(y)) => {
+                self.emit(Instruction::SkipIfRegistersNotEqual { x, y })
+            }
+            Condition::NotEqual(x, Operand::Value(value)) => {
+                self.emit(Instruction::SkipIfEqual { x, value })
+            }
+            Condition::NotEqual(x, Operand::Register(y)) => {
+                self.emit(Instruction::SkipIfRegistersEqual { x, y })
+            }
+            Condition::Less(x, rhs) => compare(self, x, rhs, true, 0),
+            Condition::GreaterOrEqual(x, rhs) => compare(self, x, rhs, true, 1),
+            Condition::Greater(x, rhs) => compare(self, x, rhs, false, 0),
+            Condition::LessOrEqual(x, rhs) => compare(self, x, rhs, false, 1),
+            Condition::Key(x) => self.emit(Instruction::SkipIfNotKey { x }),
+            Condition::NotKey(x) => self.emit(Instruction::SkipIfKey { x }),
+        }
+    }
+
+    /// Points the `1NNN` jump at `offset` to `address`.
+    fn patch_jump(&mut self, offset: usize, address: u16) {
+        self.program[offset] = 0x10 | (address >> 8) as u8 & 0x0F;
+        self.program[offset + 1] = address as u8;
+    }
+
+    fn patch(&mut self, offset: usize, kind: FixupKind, address: u16) -> AssemblyResult<()> {
+        match kind {
+            FixupKind::Address => {
+                if address > 0xFFF {
+                    return self.error(format!("address 0x{address:X} is out of range"));
+                }
+                self.program[offset] = self.program[offset] & 0xF0 | (address >> 8) as u8;
+                self.program[offset + 1] = address as u8;
+            }
+            FixupKind::Long => {
+                self.program[offset..offset + 2].copy_from_slice(&address.to_be_bytes());
+            }
+            FixupKind::UnpackHigh => {
+                self.program[offset + 1] = self.program[offset + 1] & 0xF0 | (address >> 8) as u8;
+            }
+            FixupKind::UnpackLow => self.program[offset + 1] = address as u8,
+        }
+        Ok(())
+    }
+
+    fn patch_or_fixup(
+        &mut self,
+        target: &str,
+        offset: usize,
+        kind: FixupKind,
+    ) -> AssemblyResult<()> {
+        match self.lookup(target) {
+            Some(value) => {
+                let address = self.address(value, kind)?;
+                self.patch(offset, kind, address)
+            }
+            None if is_identifier(target) => {
+                self.fixups.push(Fixup {
+                    offset,
+                    kind,
+                    label: target.to_string(),
+                    line: self.line(),
+                });
+                Ok(())
+            }
+            None => self.error(format!("expected an address but found '{target}'")),
+        }
+    }
+
+    fn define_macro(&mut self) -> AssemblyResult<()> {
+        let name = self.next()?;
+        let mut arguments = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            arguments.push(token);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let Some(token) = self.tokens.get(self.position).cloned() else {
+                return self.error(format!("macro '{name}' is never closed"));
+            };
+            self.position += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { arguments, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> AssemblyResult<()> {
+        let Macro { arguments, body } = &self.macros[name];
+        let line = self.line();
+        let depth = self.tokens[self.position - 1].depth + 1;
+        if depth > MAX_MACRO_DEPTH {
+            return self.error(format!(
+                "macro '{name}' is nested more than {MAX_MACRO_DEPTH} deep, does it expand itself?"
+            ));
+        }
+        let mut values = HashMap::new();
+        for (index, argument) in arguments.iter().enumerate() {
+            match self.tokens.get(self.position + index) {
+                Some(token) => values.insert(argument.clone(), token.text.clone()),
+                None => return self.error(format!("macro '{name}' is missing arguments")),
+            };
+        }
+        let expansion: Vec<Token> = body
+            .iter()
+            .map(|token| Token {
+                text: values.get(&token.text).unwrap_or(&token.text).clone(),
+                line,
+                depth,
+            })
+            .collect();
+        let start = self.position;
+        self.tokens
+            .splice(start..start + arguments.len(), expansion);
+        Ok(())
+    }
+
+    /// Evaluates a `:calc` expression up until the closing `}`.
+    fn calc(&mut self) -> AssemblyResult<f64> {
+        let value = self.calc_binary(0)?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_binary(&mut self, min_precedence: u8) -> AssemblyResult<f64> {
+        let mut lhs = self.calc_unary()?;
+        while let Some(precedence) = self.peek().and_then(binary_precedence) {
+            if precedence < min_precedence {
+                break;
+            }
+            let operator = self.next()?;
+            let rhs = self.calc_binary(precedence + 1)?;
+            let (a, b) = (lhs as i64, rhs as i64);
+            lhs = match operator.as_str() {
+                "+" => lhs + rhs,
+                "-" => lhs - rhs,
+                "*" => lhs * rhs,
+                "/" => lhs / rhs,
+                "%" => lhs % rhs,
+                "pow" => lhs.powf(rhs),
+                "&" => (a & b) as f64,
+                "|" => (a | b) as f64,
+                "^" => (a ^ b) as f64,
+                "<<" | ">>" => {
+                    let shifted = u32::try_from(b).ok().and_then(|b| {
+                        if operator == "<<" {
+                            a.checked_shl(b)
+                        } else {
+                            a.checked_shr(b)
+                        }
+                    });
+                    match shifted {
+                        Some(value) => value as f64,
+                        None => return self.error(format!("cannot shift by {b}")),
+                    }
+                }
+                "<" => (lhs < rhs) as u8 as f64,
+                "<=" => (lhs <= rhs) as u8 as f64,
+                ">" => (lhs > rhs) as u8 as f64,
+                ">=" => (lhs >= rhs) as u8 as f64,
+                "==" => (lhs == rhs) as u8 as f64,
+                "!=" => (lhs != rhs) as u8 as f64,
+                "min" => lhs.min(rhs),
+                _ => lhs.max(rhs),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn calc_unary(&mut self) -> AssemblyResult<f64> {
+        let token = self.next()?;
+        let value = match token.as_str() {
+            "(" => {
+                let value = self.calc_binary(0)?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.calc_unary()?,
+            "~" => !(self.calc_unary()? as i64) as f64,
+            "!" => (self.calc_unary()? == 0.) as u8 as f64,
+            "sin" => self.calc_unary()?.sin(),
+            "cos" => self.calc_unary()?.cos(),
+            "tan" => self.calc_unary()?.tan(),
+            "exp" => self.calc_unary()?.exp(),
+            "log" => self.calc_unary()?.ln(),
+            "abs" => self.calc_unary()?.abs(),
+            "sqrt" => self.calc_unary()?.sqrt(),
+            "sign" => self.calc_unary()?.signum(),
+            "ceil" => self.calc_unary()?.ceil(),
+            "floor" => self.calc_unary()?.floor(),
+            "@" => {
+                let address = self.calc_unary()? as i64;
+                let offset = address - PROGRAM_START as i64;
+                match usize::try_from(offset)
+                    .ok()
+                    .and_then(|offset| self.program.get(offset))
+                {
+                    Some(byte) => *byte as f64,
+                    None => 0.,
+                }
+            }
+            "HERE" => self.here as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            _ => match self.lookup(&token) {
+                Some(value) => value,
+                None => return self.error(format!("unknown name '{token}' in expression")),
+            },
+        };
+        Ok(value)
+    }
+
+    fn finish(mut self) -> AssemblyResult<Assembly> {
+        if let Some(block) = self.blocks.last() {
+            let open = match block {
+                Block::Loop { .. } => "loop",
+                _ => "if ... begin",
+            };
+            return self.error(format!("'{open}' is never closed"));
+        }
+        if self.main_jump {
+            let Some(&main) = self.labels.get("main") else {
+                return self.error("program does not define a 'main' label");
+            };
+            self.patch_jump(0, main);
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&address) = self.labels.get(&fixup.label) else {
+                return Err(AssemblyError {
+                    line: fixup.line,
+                    message: format!("undefined name '{}'", fixup.label),
+                });
+            };
+            self.patch(fixup.offset, fixup.kind, address)
+                .map_err(|error| AssemblyError {
+                    line: fixup.line,
+                    ..error
+                })?;
+        }
+        Ok(Assembly {
+            program: self.program,
+            symbols: self.labels.into_iter().collect(),
+        })
+    }
+}
+
+fn binary_precedence(operator: &str) -> Option<u8> {
+    let precedence = match operator {
+        "min" | "max" => 1,
+        "|" => 2,
+        "^" => 3,
+        "&" => 4,
+        "==" | "!=" => 5,
+        "<" | "<=" | ">" | ">=" => 6,
+        "<<" | ">>" => 7,
+        "+" | "-" => 8,
+        "*" | "/" | "%" | "pow" => 9,
+        _ => return None,
+    };
+    Some(precedence)
+}
+
+fn is_identifier(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Disassembly;
+
+    fn program(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().program
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source).err().unwrap().message
+    }
+
+    #[test]
+    fn disassembly_assembles_to_the_same_program() {
+        let source = ": main
+            v0 := 5
+            i := sprite
+            loop
+                sprite v0 v1 3
+                v1 += 1
+                if v1 != 10 then
+            again
+            if v0 key begin
+                v2 <<= v3
+                i := long far
+            else
+                scroll-down 4
+                plane 3
+            end
+            save v0 - v3
+            jump main
+            : sprite 0x80 0xC0 0xE0
+            :org 0x1000
+            : far 0xFF";
+        let original = program(source);
+        let disassembly = Disassembly::new(&original).to_string();
+        assert_eq!(program(&disassembly), original, "{disassembly}");
+    }
+
+    #[test]
+    fn forward_pointers_are_patched() {
+        assert_eq!(
+            program(": main :pointer data v0 := 1 jump main : data 0x12 0x34"),
+            [0x02, 0x06, 0x60, 0x01, 0x12, 0x00, 0x12, 0x34]
+        );
+        assert_eq!(
+            program(": main i := long data : data 0x12"),
+            [0xF0, 0x00, 0x02, 0x04, 0x12]
+        );
+    }
+
+    #[test]
+    fn code_below_the_program_is_rejected() {
+        let below = "cannot emit code below 0x200";
+        assert_eq!(error(":org 0x100\n:pointer main\n: main\n"), below);
+        assert_eq!(error(":org 0x100\njump later\n: later\n"), below);
+        assert_eq!(error(":org 0x100\n:unpack 0xA later\n: later\n"), below);
+        assert_eq!(error(":org 0x100\nnative later\n: later\n"), below);
+    }
+
+    #[test]
+    fn calc_evaluates_expressions() {
+        assert_eq!(
+            program(": main :calc x { ( 1 + 2 ) * 4 - 1 << 1 } :byte x :byte { x min 3 }"),
+            [0x16, 0x03]
+        );
+        assert_eq!(error(":calc x { 1 << 64 }"), "cannot shift by 64");
+        assert_eq!(error(":calc x { 1 >> -1 }"), "cannot shift by -1");
+    }
+
+    #[test]
+    fn macros_are_expanded_with_arguments() {
+        assert_eq!(
+            program(": main :macro add reg value { reg += value } add v1 2 add v3 4"),
+            [0x71, 0x02, 0x73, 0x04]
+        );
+        assert_eq!(
+            error(":macro m { m } m"),
+            "macro 'm' is nested more than 64 deep, does it expand itself?"
+        );
+    }
+}
@@ -1,5 +1,40 @@
+/// Frequency of the default tone in Hz.
+pub(crate) const DEFAULT_FREQUENCY: f32 = 440.;
+/// Seconds the sound takes to ramp up to full volume by default.
+pub(crate) const DEFAULT_ATTACK: f32 = 0.002;
+/// Seconds the sound takes to ramp down to silence by default.
+pub(crate) const DEFAULT_RELEASE: f32 = 0.01;
+
+/// Shape of the default tone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    #[default]
+    Sine,
+    Triangle,
+    /// Pseudo-random noise, changing value twice per period.
+    Noise,
+}
+
 pub trait Chip8Beeper {
     fn new(volume: f32) -> Self;
     fn play(&mut self);
     fn pause(&mut self);
+    /// Switches from the default tone to an XO-CHIP 1-bit audio pattern of 128 samples, played
+    /// back most significant bit first at `playback_rate` samples per second. A pattern of all
+    /// zeros, which is what machines start with, switches back to the default tone.
+    fn set_pattern(&mut self, pattern: &[u8; 16], playback_rate: f32);
+    /// Volume from 0 to 1.
+    fn volume(&self) -> f32;
+    fn set_volume(&mut self, volume: f32);
+    /// Sets the frequency of the default tone in Hz.
+    fn set_frequency(&mut self, frequency: f32);
+    fn set_waveform(&mut self, waveform: Waveform);
+    /// Sets how many seconds the sound takes to ramp up when it starts and down when it stops.
+    fn set_envelope(&mut self, attack: f32, release: f32);
+}
+
+/// XO-CHIP playback rate in samples per second for a value of the pitch register.
+pub fn playback_rate(pitch: u8) -> f32 {
+    4000. * 2f32.powf((pitch as f32 - 64.) / 48.)
 }
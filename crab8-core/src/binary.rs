@@ -0,0 +1,108 @@
+use crate::{Chip8Error, Chip8Result, Quirks, CHIP8_MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+
+/// Writes the big-endian binary formats of save states and movies.
+#[derive(Default)]
+pub(crate) struct Writer(pub Vec<u8>);
+
+impl Writer {
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn quirks(&mut self, quirks: &Quirks) {
+        let flags = [
+            quirks.shift_uses_vy,
+            quirks.load_store_increments_index,
+            quirks.logic_resets_flag,
+            quirks.jump_uses_vx,
+            quirks.display_wait,
+            quirks.index_overflow_sets_flag,
+            quirks.index_skips_last_register,
+            quirks.wrap_sprites,
+        ];
+        self.u8(flags
+            .into_iter()
+            .enumerate()
+            .fold(0, |bits, (i, flag)| bits | (flag as u8) << i));
+        self.u32(quirks.memory_size as u32);
+    }
+}
+
+/// Reads what [`Writer`] wrote.
+pub(crate) struct Reader<'a> {
+    pub bytes: &'a [u8],
+    /// Builds the error returned for malformed data.
+    pub invalid: fn(&'static str) -> Chip8Error,
+}
+
+impl<'a> Reader<'a> {
+    pub fn bytes(&mut self, len: usize) -> Chip8Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err((self.invalid)("unexpected end of file"));
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Chip8Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Chip8Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Chip8Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Chip8Result<u64> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn quirks(&mut self) -> Chip8Result<Quirks> {
+        let flags = self.u8()?;
+        let flag = |i: u8| flags & 1 << i != 0;
+        let memory_size = self.u32()? as usize;
+        if !(CHIP8_MEMORY_SIZE..=XO_CHIP_MEMORY_SIZE).contains(&memory_size) {
+            return Err((self.invalid)("unsupported memory size"));
+        }
+        Ok(Quirks {
+            shift_uses_vy: flag(0),
+            load_store_increments_index: flag(1),
+            logic_resets_flag: flag(2),
+            jump_uses_vx: flag(3),
+            display_wait: flag(4),
+            index_overflow_sets_flag: flag(5),
+            index_skips_last_register: flag(6),
+            wrap_sprites: flag(7),
+            memory_size,
+        })
+    }
+
+    /// Fails if there is data left after the end of the format.
+    pub fn finish(&self) -> Chip8Result<()> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err((self.invalid)("trailing data"))
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::{Duration, Instant};
+
+/// The source of time that paces the CPU and the 60 Hz timers.
+pub trait Chip8Clock {
+    fn new() -> Self;
+    /// Time passed since the clock was created.
+    fn now(&self) -> Duration;
+    /// Called for every instruction executed, with the configured clock speed in Hz.
+    fn advance(&mut self, clock_speed: u32);
+    /// How long to wait for `deadline` to come, as returned by [`Self::now`].
+    fn time_until(&self, deadline: Duration) -> Duration {
+        deadline.saturating_sub(self.now())
+    }
+}
+
+/// Wall-clock time, to run programs at their real speed. This is the default.
+pub struct RealTimeClock {
+    start: Instant,
+}
+
+impl Chip8Clock for RealTimeClock {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn advance(&mut self, _clock_speed: u32) {}
+}
+
+/// Time that only passes by executing instructions, so programs run as fast as possible and
+/// every run with the same input takes the exact same course.
+#[derive(Default)]
+pub struct VirtualClock {
+    pub elapsed: Duration,
+}
+
+impl Chip8Clock for VirtualClock {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn now(&self) -> Duration {
+        self.elapsed
+    }
+
+    fn advance(&mut self, clock_speed: u32) {
+        self.elapsed += Duration::from_nanos(1_000_000_000 / clock_speed.max(1) as u64);
+    }
+
+    fn time_until(&self, _deadline: Duration) -> Duration {
+        Duration::ZERO
+    }
+}
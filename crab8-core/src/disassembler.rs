@@ -0,0 +1,255 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use crate::{Instruction, PROGRAM_START};
+
+/// A CHIP-8 program split into code and data by following every jump and call from the entry
+/// point.
+///
+/// Its `Display` implementation prints Octo source that assembles back to the exact same bytes,
+/// with the address and raw bytes of every line in a comment.
+pub struct Disassembly<'a> {
+    program: &'a [u8],
+    instructions: BTreeMap<u16, Instruction>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl<'a> Disassembly<'a> {
+    pub fn new(program: &'a [u8]) -> Self {
+        let mut disassembly = Self {
+            program,
+            instructions: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        };
+        disassembly.trace();
+        disassembly.add_labels();
+        disassembly
+    }
+
+    /// The instruction starting at `address`, if it is reachable code.
+    pub fn instruction(&self, address: u16) -> Option<Instruction> {
+        self.instructions.get(&address).copied()
+    }
+
+    /// The generated label for `address`, if anything refers to it.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    fn end(&self) -> u32 {
+        PROGRAM_START as u32 + self.program.len() as u32
+    }
+
+    fn decode(&self, address: u16) -> Option<Instruction> {
+        if address < PROGRAM_START || address as u32 >= self.end() {
+            return None;
+        }
+        Instruction::decode_bytes(&self.program[(address - PROGRAM_START) as usize..]).ok()
+    }
+
+    /// Whether `address` is the start of an instruction or not covered by one.
+    fn is_boundary(&self, address: u16) -> bool {
+        match self.instructions.range(..address).next_back() {
+            Some((&start, instruction)) => {
+                start as u32 + instruction.size() as u32 <= address as u32
+            }
+            None => true,
+        }
+    }
+
+    fn trace(&mut self) {
+        let mut pending = vec![PROGRAM_START];
+        let mut occupied = BTreeSet::new();
+        while let Some(address) = pending.pop() {
+            if self.instructions.contains_key(&address) {
+                continue;
+            }
+            let Some(instruction) = self.decode(address) else {
+                continue;
+            };
+            let bytes = address as u32..address as u32 + instruction.size() as u32;
+            if bytes.clone().any(|byte| occupied.contains(&byte)) {
+                continue;
+            }
+            occupied.extend(bytes);
+            self.instructions.insert(address, instruction);
+
+            let next = address.wrapping_add(instruction.size());
+            match instruction {
+                Instruction::Jump { address } | Instruction::JumpOffset { address } => {
+                    pending.push(address)
+                }
+                Instruction::Call { address } => {
+                    pending.push(address);
+                    pending.push(next);
+                }
+                Instruction::Return | Instruction::Exit => {}
+                Instruction::SkipIfEqual { .. }
+                | Instruction::SkipIfNotEqual { .. }
+                | Instruction::SkipIfRegistersEqual { .. }
+                | Instruction::SkipIfRegistersNotEqual { .. }
+                | Instruction::SkipIfKey { .. }
+                | Instruction::SkipIfNotKey { .. } => {
+                    pending.push(next);
+                    if let Some(skipped) = self.decode(next) {
+                        pending.push(next.wrapping_add(skipped.size()));
+                    } else {
+                        pending.push(next.wrapping_add(2));
+                    }
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    fn add_labels(&mut self) {
+        // Octo jumps to `main` first, which it leaves out when main is at the very start.
+        if !self.program.is_empty() {
+            self.labels.insert(PROGRAM_START, "main".to_string());
+        }
+        let mut targets = Vec::new();
+        for instruction in self.instructions.values() {
+            match *instruction {
+                Instruction::Call { address } => targets.push(("sub", address)),
+                Instruction::Jump { address } | Instruction::JumpOffset { address } => {
+                    targets.push(("label", address))
+                }
+                Instruction::SetIndex { address } | Instruction::SetIndexLong { address } => {
+                    targets.push(("data", address))
+                }
+                _ => {}
+            }
+        }
+        for (prefix, address) in targets {
+            if address < PROGRAM_START || address as u32 >= self.end() || !self.is_boundary(address)
+            {
+                continue;
+            }
+            self.labels
+                .entry(address)
+                .or_insert_with(|| format!("{prefix}_{address:03x}"));
+        }
+    }
+
+    /// An address operand, using its label when it has one.
+    fn target(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => label.to_string(),
+            None => format!("0x{address:03X}"),
+        }
+    }
+
+    /// Formats an instruction as an Octo statement.
+    fn statement(&self, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::Clear => "clear".to_string(),
+            Instruction::Return => "return".to_string(),
+            Instruction::ScrollDown { rows } => format!("scroll-down {rows}"),
+            Instruction::ScrollUp { rows } => format!("scroll-up {rows}"),
+            Instruction::ScrollRight => "scroll-right".to_string(),
+            Instruction::ScrollLeft => "scroll-left".to_string(),
+            Instruction::Exit => "exit".to_string(),
+            Instruction::LowResolution => "lores".to_string(),
+            Instruction::HighResolution => "hires".to_string(),
+            Instruction::Jump { address } => format!("jump {}", self.target(address)),
+            Instruction::Call { address } => match self.label(address) {
+                Some(label) => label.to_string(),
+                None => format!(":call 0x{address:03X}"),
+            },
+            Instruction::SkipIfEqual { x, value } => format!("if v{x:x} != 0x{value:02X} then"),
+            Instruction::SkipIfNotEqual { x, value } => {
+                format!("if v{x:x} == 0x{value:02X} then")
+            }
+            Instruction::SkipIfRegistersEqual { x, y } => format!("if v{x:x} != v{y:x} then"),
+            Instruction::StoreRange { x, y } => format!("save v{x:x} - v{y:x}"),
+            Instruction::LoadRange { x, y } => format!("load v{x:x} - v{y:x}"),
+            Instruction::SetImmediate { x, value } => format!("v{x:x} := 0x{value:02X}"),
+            Instruction::AddImmediate { x, value } => format!("v{x:x} += 0x{value:02X}"),
+            Instruction::Set { x, y } => format!("v{x:x} := v{y:x}"),
+            Instruction::Or { x, y } => format!("v{x:x} |= v{y:x}"),
+            Instruction::And { x, y } => format!("v{x:x} &= v{y:x}"),
+            Instruction::Xor { x, y } => format!("v{x:x} ^= v{y:x}"),
+            Instruction::Add { x, y } => format!("v{x:x} += v{y:x}"),
+            Instruction::Subtract { x, y } => format!("v{x:x} -= v{y:x}"),
+            Instruction::ShiftRight { x, y } => format!("v{x:x} >>= v{y:x}"),
+            Instruction::SubtractReversed { x, y } => format!("v{x:x} =- v{y:x}"),
+            Instruction::ShiftLeft { x, y } => format!("v{x:x} <<= v{y:x}"),
+            Instruction::SkipIfRegistersNotEqual { x, y } => format!("if v{x:x} == v{y:x} then"),
+            Instruction::SetIndex { address } => format!("i := {}", self.target(address)),
+            Instruction::JumpOffset { address } => format!("jump0 {}", self.target(address)),
+            Instruction::Random { x, value } => format!("v{x:x} := random 0x{value:02X}"),
+            Instruction::Draw { x, y, height } => format!("sprite v{x:x} v{y:x} {height}"),
+            Instruction::SkipIfKey { x } => format!("if v{x:x} -key then"),
+            Instruction::SkipIfNotKey { x } => format!("if v{x:x} key then"),
+            Instruction::SetIndexLong { address } => match self.label(address) {
+                Some(label) => format!("i := long {label}"),
+                None => format!("i := long 0x{address:04X}"),
+            },
+            Instruction::SelectPlanes { planes } => format!("plane {planes}"),
+            Instruction::LoadAudioPattern => "audio".to_string(),
+            Instruction::GetDelay { x } => format!("v{x:x} := delay"),
+            Instruction::WaitKey { x } => format!("v{x:x} := key"),
+            Instruction::SetDelay { x } => format!("delay := v{x:x}"),
+            Instruction::SetSound { x } => format!("buzzer := v{x:x}"),
+            Instruction::AddIndex { x } => format!("i += v{x:x}"),
+            Instruction::Font { x } => format!("i := hex v{x:x}"),
+            Instruction::BigFont { x } => format!("i := bighex v{x:x}"),
+            Instruction::Bcd { x } => format!("bcd v{x:x}"),
+            Instruction::SetPitch { x } => format!("pitch := v{x:x}"),
+            Instruction::Store { x } => format!("save v{x:x}"),
+            Instruction::Load { x } => format!("load v{x:x}"),
+            Instruction::StoreFlags { x } => format!("saveflags v{x:x}"),
+            Instruction::LoadFlags { x } => format!("loadflags v{x:x}"),
+        }
+    }
+}
+
+/// Maximum number of data bytes printed on a single line.
+const DATA_BYTES_PER_LINE: usize = 8;
+
+fn write_line(
+    f: &mut fmt::Formatter<'_>,
+    statement: &str,
+    address: u16,
+    bytes: &[u8],
+) -> fmt::Result {
+    let hex: Vec<_> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+    writeln!(f, "\t{statement:<40}# {address:03X}: {}", hex.join(" "))
+}
+
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+        while offset < self.program.len() {
+            let address = PROGRAM_START + offset as u16;
+            if let Some(label) = self.label(address) {
+                writeln!(f, ": {label}")?;
+            }
+            if let Some(instruction) = self.instruction(address) {
+                let size = instruction.size() as usize;
+                let bytes = &self.program[offset..offset + size];
+                write_line(f, &self.statement(instruction), address, bytes)?;
+                offset += size;
+            } else {
+                // Data runs until the next line, label or instruction.
+                let mut end = offset + 1;
+                while end < self.program.len()
+                    && end - offset < DATA_BYTES_PER_LINE
+                    && !self.labels.contains_key(&(PROGRAM_START + end as u16))
+                    && !self
+                        .instructions
+                        .contains_key(&(PROGRAM_START + end as u16))
+                {
+                    end += 1;
+                }
+                let bytes = &self.program[offset..end];
+                let literals: Vec<_> = bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+                write_line(f, &literals.join(" "), address, bytes)?;
+                offset = end;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
 use std::io;
 
+use crate::Framebuffer;
+
 pub trait Chip8Display {
     fn new() -> Self;
-    fn clear(&mut self) -> io::Result<()>;
-    fn draw(&mut self, x: u8, y: u8, data: &[u8]) -> io::Result<bool>;
-    fn flush(&mut self) -> io::Result<()>;
+    /// Shows the contents of `framebuffer`. Called once per frame, at vertical blank.
+    fn present(&mut self, framebuffer: &Framebuffer) -> io::Result<()>;
 }
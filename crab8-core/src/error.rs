@@ -0,0 +1,87 @@
+use std::{error::Error, fmt, io};
+
+/// Everything that can stop a running CHIP-8 program.
+///
+/// Faults caused by the ROM carry the address of the faulting instruction (`pc`) and its opcode
+/// so a frontend can report where things went wrong.
+#[derive(Debug)]
+pub enum Chip8Error {
+    /// The opcode doesn't decode to any supported instruction.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// A `2NNN` call was made with the call stack already full.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// A `00EE` return was made with an empty call stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// The instruction tried to access memory past the end of RAM.
+    MemoryOutOfBounds {
+        pc: u16,
+        opcode: u16,
+        address: usize,
+    },
+    /// The program counter ran past the end of RAM.
+    ProgramCounterOutOfBounds { pc: u16 },
+    /// The program doesn't fit in RAM after the reserved interpreter area.
+    ProgramTooLarge { size: usize, max_size: usize },
+    /// A save state is corrupt or truncated.
+    InvalidSnapshot { reason: &'static str },
+    /// A save state was written in a newer format than this version understands.
+    UnsupportedSnapshotVersion { version: u16 },
+    /// A movie file is corrupt, truncated or doesn't belong to the ROM.
+    InvalidMovie { reason: &'static str },
+    /// A frontend or file operation failed.
+    Io(io::Error),
+}
+
+pub type Chip8Result<T> = Result<T, Chip8Error>;
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {opcode:04X} at {pc:03X}")
+            }
+            Chip8Error::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow by {opcode:04X} at {pc:03X}")
+            }
+            Chip8Error::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow by {opcode:04X} at {pc:03X}")
+            }
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "out of bounds memory access to {address:04X} by {opcode:04X} at {pc:03X}"
+            ),
+            Chip8Error::ProgramCounterOutOfBounds { pc } => {
+                write!(f, "program counter ran out of memory at {pc:04X}")
+            }
+            Chip8Error::ProgramTooLarge { size, max_size } => write!(
+                f,
+                "program is {size} bytes but at most {max_size} bytes fit in memory"
+            ),
+            Chip8Error::InvalidSnapshot { reason } => write!(f, "invalid save state: {reason}"),
+            Chip8Error::UnsupportedSnapshotVersion { version } => {
+                write!(f, "save state version {version} is not supported")
+            }
+            Chip8Error::InvalidMovie { reason } => write!(f, "invalid movie: {reason}"),
+            Chip8Error::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Chip8Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Self {
+        Chip8Error::Io(error)
+    }
+}
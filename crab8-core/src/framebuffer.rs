@@ -0,0 +1,258 @@
+use std::fmt;
+
+/// Width of the screen in the SUPER-CHIP high resolution mode.
+pub const MAX_WIDTH: usize = 128;
+/// Height of the screen in the SUPER-CHIP high resolution mode.
+pub const MAX_HEIGHT: usize = 64;
+
+/// The CHIP-8 screen, implementing the drawing rules every frontend shares: XOR drawing with
+/// collision detection, clipping, scrolling and the XO-CHIP bitplanes.
+///
+/// Every pixel is a byte holding the bitplanes it is set in, so 0 is off and 1 is the only
+/// colour outside of XO-CHIP.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer {
+    high_resolution: bool,
+    selected_planes: u8,
+    pixels: Vec<u8>,
+}
+
+/// Characters used to print a pixel, indexed by the bitplanes it is set in.
+const PIXEL_CHARS: [char; 4] = ['.', '#', '+', '@'];
+
+/// Prints the screen as text, one line per row, with `.` for pixels that are off and `#` for
+/// pixels that are on. XO-CHIP pixels only on the second plane print as `+` and pixels on both
+/// planes as `@`.
+impl fmt::Display for Framebuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.pixels().chunks(self.width()) {
+            let line: String = row
+                .iter()
+                .map(|pixel| PIXEL_CHARS[*pixel as usize & 0b11])
+                .collect();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framebuffer {
+    pub fn new() -> Self {
+        Self {
+            high_resolution: false,
+            selected_planes: 1,
+            pixels: vec![0; MAX_WIDTH * MAX_HEIGHT],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.high_resolution {
+            MAX_WIDTH
+        } else {
+            MAX_WIDTH / 2
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.high_resolution {
+            MAX_HEIGHT
+        } else {
+            MAX_HEIGHT / 2
+        }
+    }
+
+    /// Whether the SUPER-CHIP 128x64 mode is active.
+    pub fn high_resolution(&self) -> bool {
+        self.high_resolution
+    }
+
+    /// The bitplanes set for the pixel at `x`, `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width() + x]
+    }
+
+    /// All pixels, row by row at the current resolution.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels[..self.width() * self.height()]
+    }
+
+    /// Replaces the pixels with ones in the layout returned by [`Self::pixels`].
+    pub fn set_pixels(&mut self, pixels: &[u8]) {
+        self.pixels.fill(0);
+        let len = pixels.len().min(self.width() * self.height());
+        self.pixels[..len].copy_from_slice(&pixels[..len]);
+    }
+
+    /// Switches between the 64x32 and the SUPER-CHIP 128x64 resolution, clearing the screen.
+    pub fn set_high_resolution(&mut self, enabled: bool) {
+        self.high_resolution = enabled;
+        self.pixels.fill(0);
+    }
+
+    /// The XO-CHIP bitplanes that drawing, clearing and scrolling apply to.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes;
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !self.selected_planes;
+        }
+    }
+
+    /// Draws an 8 pixel wide sprite with one byte per row. When several planes are selected,
+    /// `data` holds the rows for each selected plane one after the other. Parts of the sprite
+    /// past the edge of the screen wrap around if `wrap` is set, and are clipped otherwise.
+    /// Returns whether a pixel was turned off.
+    pub fn draw(&mut self, x: u8, y: u8, data: &[u8], wrap: bool) -> bool {
+        self.draw_planes(x, y, 8, data, wrap)
+    }
+
+    /// Draws a 16x16 SUPER-CHIP sprite with two bytes per row, laid out per plane like
+    /// [`Self::draw`].
+    pub fn draw_large(&mut self, x: u8, y: u8, data: &[u8], wrap: bool) -> bool {
+        self.draw_planes(x, y, 16, data, wrap)
+    }
+
+    pub fn scroll_down(&mut self, rows: u8) {
+        self.shift(0, rows as isize)
+    }
+
+    pub fn scroll_up(&mut self, rows: u8) {
+        self.shift(0, -(rows as isize))
+    }
+
+    /// Scrolls the screen 4 pixels to the left.
+    pub fn scroll_left(&mut self) {
+        self.shift(-4, 0)
+    }
+
+    /// Scrolls the screen 4 pixels to the right.
+    pub fn scroll_right(&mut self) {
+        self.shift(4, 0)
+    }
+
+    /// Draws a `width` pixel wide sprite on every selected plane, `data` holding the rows for
+    /// each plane one after the other.
+    fn draw_planes(&mut self, x: u8, y: u8, width: usize, data: &[u8], wrap: bool) -> bool {
+        let planes: Vec<u8> = [0b01, 0b10]
+            .into_iter()
+            .filter(|plane| self.selected_planes & plane != 0)
+            .collect();
+        if planes.is_empty() {
+            return false;
+        }
+        let bytes_per_row = width / 8;
+        let plane_len = data.len() / planes.len();
+        let mut pixel_cleared = false;
+        for (plane, plane_data) in planes.into_iter().zip(data.chunks(plane_len.max(1))) {
+            let rows = plane_data.chunks_exact(bytes_per_row).map(|row| match row {
+                [byte] => (*byte as u16) << 8,
+                _ => u16::from_be_bytes([row[0], row[1]]),
+            });
+            pixel_cleared |= self.draw_rows(x, y, width, plane, rows, wrap);
+        }
+        pixel_cleared
+    }
+
+    /// XORs a sprite onto one plane. Every row is `width` pixels wide, most significant bit
+    /// first. The sprite's origin wraps around the screen, and the sprite itself only does if
+    /// `wrap` is set.
+    fn draw_rows(
+        &mut self,
+        x: u8,
+        y: u8,
+        width: usize,
+        plane: u8,
+        rows: impl Iterator<Item = u16>,
+        wrap: bool,
+    ) -> bool {
+        let (screen_width, screen_height) = (self.width(), self.height());
+        let x = x as usize % screen_width;
+        let y = y as usize % screen_height;
+        let mut pixel_cleared = false;
+        for (i, to_draw) in rows.enumerate() {
+            let mut row = y + i;
+            if row >= screen_height {
+                if !wrap {
+                    break;
+                }
+                row %= screen_height;
+            }
+            for j in 0..width {
+                let mut col = x + j;
+                if col >= screen_width {
+                    if !wrap {
+                        break;
+                    }
+                    col %= screen_width;
+                }
+                if to_draw & (1 << (15 - j)) == 0 {
+                    continue;
+                }
+                let pixel = &mut self.pixels[row * screen_width + col];
+                if *pixel & plane != 0 {
+                    pixel_cleared = true;
+                }
+                *pixel ^= plane;
+            }
+        }
+        pixel_cleared
+    }
+
+    /// Moves the selected planes by `dx`, `dy` pixels, filling the uncovered area with blank
+    /// pixels.
+    fn shift(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width(), self.height());
+        let planes = self.selected_planes;
+        let old = self.pixels.clone();
+        for row in 0..height {
+            for col in 0..width {
+                let source_row = row as isize - dy;
+                let source_col = col as isize - dx;
+                let source = if (0..height as isize).contains(&source_row)
+                    && (0..width as isize).contains(&source_col)
+                {
+                    old[source_row as usize * width + source_col as usize]
+                } else {
+                    0
+                };
+                let pixel = &mut self.pixels[row * width + col];
+                *pixel = (*pixel & !planes) | (source & planes);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprites_are_clipped_or_wrapped_at_the_edges() {
+        let mut clipped = Framebuffer::new();
+        clipped.draw(62, 31, &[0xFF, 0xFF], false);
+        let lit = |framebuffer: &Framebuffer| {
+            (0..32)
+                .flat_map(|y| (0..64).map(move |x| (x, y)))
+                .filter(|&(x, y)| framebuffer.pixel(x, y) != 0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lit(&clipped), [(62, 31), (63, 31)]);
+
+        let mut wrapped = Framebuffer::new();
+        wrapped.draw(62, 31, &[0xC1, 0x80], true);
+        assert_eq!(lit(&wrapped), [(62, 0), (5, 31), (62, 31), (63, 31)]);
+    }
+}
@@ -0,0 +1,254 @@
+use std::{collections::BTreeMap, io};
+
+use crate::{
+    beeper::{DEFAULT_ATTACK, DEFAULT_FREQUENCY, DEFAULT_RELEASE},
+    Chip8Beeper, Chip8Display, Chip8Interpreter, Chip8Keyboard, Framebuffer, VirtualClock,
+    Waveform, XorShiftRng,
+};
+
+/// An interpreter that runs without a terminal or audio device, for tests and tools. It runs on
+/// a [`VirtualClock`], so it goes as fast as possible and always takes the same course.
+pub type HeadlessInterpreter =
+    Chip8Interpreter<HeadlessDisplay, ScriptedKeyboard, NullBeeper, XorShiftRng, VirtualClock>;
+
+/// A display that keeps the last presented frame in memory.
+#[derive(Default)]
+pub struct HeadlessDisplay {
+    /// The last presented frame.
+    pub framebuffer: Framebuffer,
+    /// Number of frames presented so far.
+    pub frames: u64,
+}
+
+impl Chip8Display for HeadlessDisplay {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn present(&mut self, framebuffer: &Framebuffer) -> io::Result<()> {
+        self.framebuffer.clone_from(framebuffer);
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+/// A keyboard that plays back key presses and releases scheduled for specific frames.
+///
+/// Every call to `update_keystates` is one frame, which matches
+/// [`Chip8Interpreter::run_frame`]. Events scheduled for frame N take effect right before the
+/// instructions of frame N run, counting from 0.
+#[derive(Default)]
+pub struct ScriptedKeyboard {
+    /// Key states to switch to, by frame.
+    events: BTreeMap<u64, Vec<(u8, bool)>>,
+    frame: u64,
+    key_states: u16,
+    last_key_pressed: Option<u8>,
+}
+
+impl ScriptedKeyboard {
+    /// Presses `key` at the start of `frame`. Panics if `key` isn't a hex key from 0 to F.
+    pub fn press(&mut self, frame: u64, key: u8) {
+        assert!(key < 16, "{key} isn't a hex key from 0 to F");
+        self.events.entry(frame).or_default().push((key, true));
+    }
+
+    /// Releases `key` at the start of `frame`. Panics if `key` isn't a hex key from 0 to F.
+    pub fn release(&mut self, frame: u64, key: u8) {
+        assert!(key < 16, "{key} isn't a hex key from 0 to F");
+        self.events.entry(frame).or_default().push((key, false));
+    }
+
+    /// Number of frames played back so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+impl Chip8Keyboard for ScriptedKeyboard {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_keystates(&mut self, _max_duration_microseconds: u64) -> io::Result<()> {
+        self.last_key_pressed = None;
+        for (key, pressed) in self.events.remove(&self.frame).unwrap_or_default() {
+            if pressed {
+                if self.key_states & 1 << key == 0 {
+                    self.last_key_pressed = Some(key);
+                }
+                self.key_states |= 1 << key;
+            } else {
+                self.key_states &= !(1 << key);
+            }
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn is_key_down(&self, key: u8) -> bool {
+        key < 16 && self.key_states & (1 << key) != 0
+    }
+
+    fn last_key_pressed(&self) -> Option<u8> {
+        self.last_key_pressed
+    }
+
+    fn key_states(&self) -> u16 {
+        self.key_states
+    }
+
+    fn set_key_states(&mut self, key_states: u16) {
+        self.key_states = key_states;
+    }
+}
+
+/// A beeper that makes no sound, but remembers what it was asked to play.
+pub struct NullBeeper {
+    pub playing: bool,
+    pub volume: f32,
+    pub frequency: f32,
+    pub waveform: Waveform,
+    /// Seconds the sound takes to ramp up and down.
+    pub envelope: (f32, f32),
+    /// The last XO-CHIP audio pattern and playback rate that was set, or `None` while the
+    /// default tone plays.
+    pub pattern: Option<([u8; 16], f32)>,
+}
+
+impl Chip8Beeper for NullBeeper {
+    fn new(volume: f32) -> Self {
+        Self {
+            playing: false,
+            volume,
+            frequency: DEFAULT_FREQUENCY,
+            waveform: Waveform::default(),
+            envelope: (DEFAULT_ATTACK, DEFAULT_RELEASE),
+            pattern: None,
+        }
+    }
+
+    fn play(&mut self) {
+        self.playing = true;
+    }
+
+    fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], playback_rate: f32) {
+        self.pattern = (*pattern != [0; 16]).then_some((*pattern, playback_rate));
+    }
+
+    fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    fn set_envelope(&mut self, attack: f32, release: f32) {
+        self.envelope = (attack, release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    #[test]
+    fn keys_above_f_are_never_down() {
+        let mut keyboard = ScriptedKeyboard::new();
+        keyboard.set_key_states(0xFFFF);
+        assert!(keyboard.is_key_down(0xF));
+        assert!(!keyboard.is_key_down(0x10));
+        assert!(!keyboard.is_key_down(0xFF));
+    }
+
+    #[test]
+    #[should_panic(expected = "16 isn't a hex key from 0 to F")]
+    fn scripting_keys_above_f_panics() {
+        ScriptedKeyboard::new().press(0, 16);
+    }
+
+    #[test]
+    fn scripted_keys_are_pressed_and_released() {
+        let mut keyboard = ScriptedKeyboard::new();
+        keyboard.press(0, 0xF);
+        keyboard.release(1, 0xF);
+        keyboard.update_keystates(0).unwrap();
+        assert_eq!(keyboard.key_states(), 0x8000);
+        assert_eq!(keyboard.last_key_pressed(), Some(0xF));
+        keyboard.update_keystates(0).unwrap();
+        assert_eq!(keyboard.key_states(), 0);
+    }
+
+    #[test]
+    fn key_skips_accept_any_register_value() {
+        let mut interpreter = HeadlessInterpreter::new(
+            700,
+            Quirks::COSMAC_VIP,
+            HeadlessDisplay::new(),
+            ScriptedKeyboard::new(),
+            NullBeeper::new(0.),
+        );
+        // v0 := 0x80, skip if key v0, skip unless key v0, jump 0x208, loop
+        let program = [0x60, 0x80, 0xE0, 0x9E, 0xE0, 0xA1, 0x12, 0x08, 0x12, 0x08];
+        interpreter.load_program(&program).unwrap();
+        interpreter.run_frames(1).unwrap();
+        assert_eq!(interpreter.state.program_counter, 0x208);
+    }
+
+    #[test]
+    fn restoring_a_snapshot_restores_the_default_tone() {
+        let mut interpreter = HeadlessInterpreter::new(
+            700,
+            Quirks::XO_CHIP,
+            HeadlessDisplay::new(),
+            ScriptedKeyboard::new(),
+            NullBeeper::new(0.),
+        );
+        // i := 0x20A, audio, loop, then the pattern
+        let mut program = vec![0xA2, 0x0A, 0xF0, 0x02, 0x12, 0x04, 0, 0, 0, 0];
+        program.extend([0xF0; 16]);
+        interpreter.load_program(&program).unwrap();
+        let snapshot = interpreter.snapshot();
+        interpreter.run_frames(1).unwrap();
+        assert!(interpreter.beeper.pattern.is_some());
+        interpreter.restore(&snapshot).unwrap();
+        assert_eq!(interpreter.beeper.pattern, None);
+    }
+
+    #[test]
+    fn chip_48_leaves_i_on_the_last_register() {
+        // i := 0x300, save v2, then the same on SUPER-CHIP and the VIP
+        let program = [0xA3, 0x00, 0xF2, 0x55];
+        for (quirks, index) in [
+            (Quirks::CHIP_48, 0x302),
+            (Quirks::SCHIP_MODERN, 0x300),
+            (Quirks::COSMAC_VIP, 0x303),
+        ] {
+            let mut interpreter = HeadlessInterpreter::new(
+                700,
+                quirks,
+                HeadlessDisplay::new(),
+                ScriptedKeyboard::new(),
+                NullBeeper::new(0.),
+            );
+            interpreter.load_program(&program).unwrap();
+            interpreter.step().unwrap();
+            interpreter.step().unwrap();
+            assert_eq!(interpreter.state.index_register, index);
+        }
+    }
+}
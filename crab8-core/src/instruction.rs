@@ -0,0 +1,385 @@
+use std::fmt;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction.
+///
+/// `x` and `y` are register indices, `address` is a memory address and `value` an immediate byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `00E0`
+    Clear,
+    /// `00EE`
+    Return,
+    /// `00CN`
+    ScrollDown { rows: u8 },
+    /// `00DN`
+    ScrollUp { rows: u8 },
+    /// `00FB`
+    ScrollRight,
+    /// `00FC`
+    ScrollLeft,
+    /// `00FD`
+    Exit,
+    /// `00FE`
+    LowResolution,
+    /// `00FF`
+    HighResolution,
+    /// `1NNN`
+    Jump { address: u16 },
+    /// `2NNN`
+    Call { address: u16 },
+    /// `3XNN`
+    SkipIfEqual { x: u8, value: u8 },
+    /// `4XNN`
+    SkipIfNotEqual { x: u8, value: u8 },
+    /// `5XY0`
+    SkipIfRegistersEqual { x: u8, y: u8 },
+    /// `5XY2`
+    StoreRange { x: u8, y: u8 },
+    /// `5XY3`
+    LoadRange { x: u8, y: u8 },
+    /// `6XNN`
+    SetImmediate { x: u8, value: u8 },
+    /// `7XNN`
+    AddImmediate { x: u8, value: u8 },
+    /// `8XY0`
+    Set { x: u8, y: u8 },
+    /// `8XY1`
+    Or { x: u8, y: u8 },
+    /// `8XY2`
+    And { x: u8, y: u8 },
+    /// `8XY3`
+    Xor { x: u8, y: u8 },
+    /// `8XY4`
+    Add { x: u8, y: u8 },
+    /// `8XY5`
+    Subtract { x: u8, y: u8 },
+    /// `8XY6`
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`
+    SubtractReversed { x: u8, y: u8 },
+    /// `8XYE`
+    ShiftLeft { x: u8, y: u8 },
+    /// `9XY0`
+    SkipIfRegistersNotEqual { x: u8, y: u8 },
+    /// `ANNN`
+    SetIndex { address: u16 },
+    /// `BNNN`
+    JumpOffset { address: u16 },
+    /// `CXNN`
+    Random { x: u8, value: u8 },
+    /// `DXYN`, where a height of 0 draws a 16x16 sprite.
+    Draw { x: u8, y: u8, height: u8 },
+    /// `EX9E`
+    SkipIfKey { x: u8 },
+    /// `EXA1`
+    SkipIfNotKey { x: u8 },
+    /// `F000 NNNN`
+    SetIndexLong { address: u16 },
+    /// `FN01`
+    SelectPlanes { planes: u8 },
+    /// `F002`
+    LoadAudioPattern,
+    /// `FX07`
+    GetDelay { x: u8 },
+    /// `FX0A`
+    WaitKey { x: u8 },
+    /// `FX15`
+    SetDelay { x: u8 },
+    /// `FX18`
+    SetSound { x: u8 },
+    /// `FX1E`
+    AddIndex { x: u8 },
+    /// `FX29`
+    Font { x: u8 },
+    /// `FX30`
+    BigFont { x: u8 },
+    /// `FX33`
+    Bcd { x: u8 },
+    /// `FX3A`
+    SetPitch { x: u8 },
+    /// `FX55`
+    Store { x: u8 },
+    /// `FX65`
+    Load { x: u8 },
+    /// `FX75`
+    StoreFlags { x: u8 },
+    /// `FX85`
+    LoadFlags { x: u8 },
+}
+
+/// Why a sequence of bytes couldn't be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The opcode isn't a known instruction.
+    UnknownOpcode(u16),
+    /// The opcode is `F000`, which is followed by a 16-bit operand. Use
+    /// [`Instruction::decode_bytes`] to decode it.
+    MissingOperand(u16),
+    /// There weren't enough bytes left to hold the instruction.
+    Truncated,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(opcode) => write!(f, "unknown opcode {opcode:04X}"),
+            DecodeError::MissingOperand(opcode) => {
+                write!(f, "opcode {opcode:04X} needs a 16-bit operand")
+            }
+            DecodeError::Truncated => write!(f, "instruction is cut off"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Instruction {
+    /// Decodes a single 2-byte opcode.
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        let [byte_a, byte_b] = opcode.to_be_bytes();
+        let nibble_0 = (byte_a & 0xF0) >> 4;
+        let nibble_1 = byte_a & 0x0F;
+        let nibble_2 = (byte_b & 0xF0) >> 4;
+        let nibble_3 = byte_b & 0x0F;
+
+        let address = opcode & 0x0FFF;
+        let value = byte_b;
+
+        let instruction = match [nibble_0, nibble_1, nibble_2, nibble_3] {
+            [0x0, 0x0, 0xE, 0x0] => Instruction::Clear,
+            [0x0, 0x0, 0xE, 0xE] => Instruction::Return,
+            [0x0, 0x0, 0xC, rows] => Instruction::ScrollDown { rows },
+            [0x0, 0x0, 0xD, rows] => Instruction::ScrollUp { rows },
+            [0x0, 0x0, 0xF, 0xB] => Instruction::ScrollRight,
+            [0x0, 0x0, 0xF, 0xC] => Instruction::ScrollLeft,
+            [0x0, 0x0, 0xF, 0xD] => Instruction::Exit,
+            [0x0, 0x0, 0xF, 0xE] => Instruction::LowResolution,
+            [0x0, 0x0, 0xF, 0xF] => Instruction::HighResolution,
+            [0x1, _, _, _] => Instruction::Jump { address },
+            [0x2, _, _, _] => Instruction::Call { address },
+            [0x3, x, _, _] => Instruction::SkipIfEqual { x, value },
+            [0x4, x, _, _] => Instruction::SkipIfNotEqual { x, value },
+            [0x5, x, y, 0x0] => Instruction::SkipIfRegistersEqual { x, y },
+            [0x5, x, y, 0x2] => Instruction::StoreRange { x, y },
+            [0x5, x, y, 0x3] => Instruction::LoadRange { x, y },
+            [0x6, x, _, _] => Instruction::SetImmediate { x, value },
+            [0x7, x, _, _] => Instruction::AddImmediate { x, value },
+            [0x8, x, y, 0x0] => Instruction::Set { x, y },
+            [0x8, x, y, 0x1] => Instruction::Or { x, y },
+            [0x8, x, y, 0x2] => Instruction::And { x, y },
+            [0x8, x, y, 0x3] => Instruction::Xor { x, y },
+            [0x8, x, y, 0x4] => Instruction::Add { x, y },
+            [0x8, x, y, 0x5] => Instruction::Subtract { x, y },
+            [0x8, x, y, 0x6] => Instruction::ShiftRight { x, y },
+            [0x8, x, y, 0x7] => Instruction::SubtractReversed { x, y },
+            [0x8, x, y, 0xE] => Instruction::ShiftLeft { x, y },
+            [0x9, x, y, 0x0] => Instruction::SkipIfRegistersNotEqual { x, y },
+            [0xA, _, _, _] => Instruction::SetIndex { address },
+            [0xB, _, _, _] => Instruction::JumpOffset { address },
+            [0xC, x, _, _] => Instruction::Random { x, value },
+            [0xD, x, y, height] => Instruction::Draw { x, y, height },
+            [0xE, x, 0x9, 0xE] => Instruction::SkipIfKey { x },
+            [0xE, x, 0xA, 0x1] => Instruction::SkipIfNotKey { x },
+            [0xF, 0x0, 0x0, 0x0] => return Err(DecodeError::MissingOperand(opcode)),
+            [0xF, planes, 0x0, 0x1] if planes < 4 => Instruction::SelectPlanes { planes },
+            [0xF, 0x0, 0x0, 0x2] => Instruction::LoadAudioPattern,
+            [0xF, x, 0x0, 0x7] => Instruction::GetDelay { x },
+            [0xF, x, 0x0, 0xA] => Instruction::WaitKey { x },
+            [0xF, x, 0x1, 0x5] => Instruction::SetDelay { x },
+            [0xF, x, 0x1, 0x8] => Instruction::SetSound { x },
+            [0xF, x, 0x1, 0xE] => Instruction::AddIndex { x },
+            [0xF, x, 0x2, 0x9] => Instruction::Font { x },
+            [0xF, x, 0x3, 0x0] => Instruction::BigFont { x },
+            [0xF, x, 0x3, 0x3] => Instruction::Bcd { x },
+            [0xF, x, 0x3, 0xA] => Instruction::SetPitch { x },
+            [0xF, x, 0x5, 0x5] => Instruction::Store { x },
+            [0xF, x, 0x6, 0x5] => Instruction::Load { x },
+            [0xF, x, 0x7, 0x5] => Instruction::StoreFlags { x },
+            [0xF, x, 0x8, 0x5] => Instruction::LoadFlags { x },
+            _ => return Err(DecodeError::UnknownOpcode(opcode)),
+        };
+        Ok(instruction)
+    }
+
+    /// Decodes the instruction at the start of `bytes`, including the operand of `F000 NNNN`.
+    pub fn decode_bytes(bytes: &[u8]) -> Result<Instruction, DecodeError> {
+        let [byte_a, byte_b, ..] = *bytes else {
+            return Err(DecodeError::Truncated);
+        };
+        match Instruction::decode(u16::from_be_bytes([byte_a, byte_b])) {
+            Err(DecodeError::MissingOperand(_)) => match *bytes {
+                [_, _, operand_a, operand_b, ..] => Ok(Instruction::SetIndexLong {
+                    address: u16::from_be_bytes([operand_a, operand_b]),
+                }),
+                _ => Err(DecodeError::Truncated),
+            },
+            result => result,
+        }
+    }
+
+    /// Encodes the instruction's opcode. For `F000 NNNN` this is only the `F000` part, see
+    /// [`Instruction::to_bytes`].
+    pub fn encode(&self) -> u16 {
+        let xy = |opcode: u16, x: u8, y: u8, n: u16| opcode | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xnn = |opcode: u16, x: u8, value: u8| opcode | (x as u16) << 8 | value as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16) << 8 | nn;
+        match *self {
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown { rows } => 0x00C0 | rows as u16,
+            Instruction::ScrollUp { rows } => 0x00D0 | rows as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowResolution => 0x00FE,
+            Instruction::HighResolution => 0x00FF,
+            Instruction::Jump { address } => 0x1000 | address,
+            Instruction::Call { address } => 0x2000 | address,
+            Instruction::SkipIfEqual { x, value } => xnn(0x3000, x, value),
+            Instruction::SkipIfNotEqual { x, value } => xnn(0x4000, x, value),
+            Instruction::SkipIfRegistersEqual { x, y } => xy(0x5000, x, y, 0x0),
+            Instruction::StoreRange { x, y } => xy(0x5000, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xy(0x5000, x, y, 0x3),
+            Instruction::SetImmediate { x, value } => xnn(0x6000, x, value),
+            Instruction::AddImmediate { x, value } => xnn(0x7000, x, value),
+            Instruction::Set { x, y } => xy(0x8000, x, y, 0x0),
+            Instruction::Or { x, y } => xy(0x8000, x, y, 0x1),
+            Instruction::And { x, y } => xy(0x8000, x, y, 0x2),
+            Instruction::Xor { x, y } => xy(0x8000, x, y, 0x3),
+            Instruction::Add { x, y } => xy(0x8000, x, y, 0x4),
+            Instruction::Subtract { x, y } => xy(0x8000, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => xy(0x8000, x, y, 0x6),
+            Instruction::SubtractReversed { x, y } => xy(0x8000, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => xy(0x8000, x, y, 0xE),
+            Instruction::SkipIfRegistersNotEqual { x, y } => xy(0x9000, x, y, 0x0),
+            Instruction::SetIndex { address } => 0xA000 | address,
+            Instruction::JumpOffset { address } => 0xB000 | address,
+            Instruction::Random { x, value } => xnn(0xC000, x, value),
+            Instruction::Draw { x, y, height } => xy(0xD000, x, y, height as u16),
+            Instruction::SkipIfKey { x } => xnn(0xE000, x, 0x9E),
+            Instruction::SkipIfNotKey { x } => xnn(0xE000, x, 0xA1),
+            Instruction::SetIndexLong { .. } => 0xF000,
+            Instruction::SelectPlanes { planes } => fx(planes, 0x01),
+            Instruction::LoadAudioPattern => 0xF002,
+            Instruction::GetDelay { x } => fx(x, 0x07),
+            Instruction::WaitKey { x } => fx(x, 0x0A),
+            Instruction::SetDelay { x } => fx(x, 0x15),
+            Instruction::SetSound { x } => fx(x, 0x18),
+            Instruction::AddIndex { x } => fx(x, 0x1E),
+            Instruction::Font { x } => fx(x, 0x29),
+            Instruction::BigFont { x } => fx(x, 0x30),
+            Instruction::Bcd { x } => fx(x, 0x33),
+            Instruction::SetPitch { x } => fx(x, 0x3A),
+            Instruction::Store { x } => fx(x, 0x55),
+            Instruction::Load { x } => fx(x, 0x65),
+            Instruction::StoreFlags { x } => fx(x, 0x75),
+            Instruction::LoadFlags { x } => fx(x, 0x85),
+        }
+    }
+
+    /// Encodes the instruction to the bytes it occupies in memory.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::SetIndexLong { address } = self {
+            bytes.extend(address.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Number of bytes the instruction occupies in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::SetIndexLong { .. } => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Clear => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown { rows } => write!(f, "SCD {rows}"),
+            Instruction::ScrollUp { rows } => write!(f, "SCU {rows}"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowResolution => write!(f, "LOW"),
+            Instruction::HighResolution => write!(f, "HIGH"),
+            Instruction::Jump { address } => write!(f, "JP 0x{address:03X}"),
+            Instruction::Call { address } => write!(f, "CALL 0x{address:03X}"),
+            Instruction::SkipIfEqual { x, value } => write!(f, "SE V{x:X}, 0x{value:02X}"),
+            Instruction::SkipIfNotEqual { x, value } => write!(f, "SNE V{x:X}, 0x{value:02X}"),
+            Instruction::SkipIfRegistersEqual { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            Instruction::StoreRange { x, y } => write!(f, "SAVE V{x:X}-V{y:X}"),
+            Instruction::LoadRange { x, y } => write!(f, "LOAD V{x:X}-V{y:X}"),
+            Instruction::SetImmediate { x, value } => write!(f, "LD V{x:X}, 0x{value:02X}"),
+            Instruction::AddImmediate { x, value } => write!(f, "ADD V{x:X}, 0x{value:02X}"),
+            Instruction::Set { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Instruction::Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            Instruction::And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Instruction::Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Instruction::Add { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Instruction::Subtract { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            Instruction::SubtractReversed { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            Instruction::SkipIfRegistersNotEqual { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            Instruction::SetIndex { address } => write!(f, "LD I, 0x{address:03X}"),
+            Instruction::JumpOffset { address } => write!(f, "JP V0, 0x{address:03X}"),
+            Instruction::Random { x, value } => write!(f, "RND V{x:X}, 0x{value:02X}"),
+            Instruction::Draw { x, y, height } => write!(f, "DRW V{x:X}, V{y:X}, {height}"),
+            Instruction::SkipIfKey { x } => write!(f, "SKP V{x:X}"),
+            Instruction::SkipIfNotKey { x } => write!(f, "SKNP V{x:X}"),
+            Instruction::SetIndexLong { address } => write!(f, "LD I, LONG 0x{address:04X}"),
+            Instruction::SelectPlanes { planes } => write!(f, "PLANE {planes}"),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO"),
+            Instruction::GetDelay { x } => write!(f, "LD V{x:X}, DT"),
+            Instruction::WaitKey { x } => write!(f, "LD V{x:X}, K"),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{x:X}"),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{x:X}"),
+            Instruction::AddIndex { x } => write!(f, "ADD I, V{x:X}"),
+            Instruction::Font { x } => write!(f, "LD F, V{x:X}"),
+            Instruction::BigFont { x } => write!(f, "LD HF, V{x:X}"),
+            Instruction::Bcd { x } => write!(f, "LD B, V{x:X}"),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{x:X}"),
+            Instruction::Store { x } => write!(f, "LD [I], V{x:X}"),
+            Instruction::Load { x } => write!(f, "LD V{x:X}, [I]"),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{x:X}"),
+            Instruction::LoadFlags { x } => write!(f, "LD V{x:X}, R"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_round_trips() {
+        for opcode in 0..=u16::MAX {
+            match Instruction::decode(opcode) {
+                Ok(instruction) => assert_eq!(
+                    instruction.encode(),
+                    opcode,
+                    "{instruction} doesn't encode to {opcode:04X}"
+                ),
+                Err(DecodeError::MissingOperand(_)) => assert_eq!(opcode, 0xF000),
+                Err(_) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn long_index_round_trips() {
+        let bytes = [0xF0, 0x00, 0x12, 0x34];
+        let instruction = Instruction::decode_bytes(&bytes).unwrap();
+        assert_eq!(instruction, Instruction::SetIndexLong { address: 0x1234 });
+        assert_eq!(instruction.to_bytes(), bytes);
+        assert_eq!(
+            Instruction::decode_bytes(&bytes[..2]),
+            Err(DecodeError::Truncated)
+        );
+    }
+}
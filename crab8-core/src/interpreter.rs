@@ -1,28 +1,26 @@
-use std::{
-    fs, io,
-    path::Path,
-    time::{Duration, Instant},
-};
-
-use rand::{thread_rng, Rng};
+use std::{fs, io, path::Path, time::Duration};
 
-use crate::{Chip8Beeper, Chip8Display, Chip8Keyboard, Chip8State};
+use crate::{
+    playback_rate, state::BIG_FONT_ADDRESS, Chip8Beeper, Chip8Clock, Chip8Display, Chip8Error,
+    Chip8Keyboard, Chip8Random, Chip8Result, Chip8State, DecodeError, Framebuffer, Instruction,
+    Quirks, RealTimeClock, RewindBuffer, Snapshot, XorShiftRng,
+};
 
 struct Timer {
     interval: Duration,
-    last_tick: Instant,
+    last_tick: Duration,
 }
 
 impl Timer {
-    fn new(interval: Duration) -> Self {
+    fn new(interval: Duration, now: Duration) -> Self {
         Self {
             interval,
-            last_tick: Instant::now(),
+            last_tick: now,
         }
     }
 
-    fn tick(&mut self) -> bool {
-        if self.last_tick.elapsed() >= self.interval {
+    fn tick(&mut self, now: Duration) -> bool {
+        if now.saturating_sub(self.last_tick) >= self.interval {
             self.last_tick += self.interval;
             true
         } else {
@@ -31,253 +29,688 @@ impl Timer {
     }
 }
 
-pub struct Chip8Interpreter<D: Chip8Display, K: Chip8Keyboard, B: Chip8Beeper> {
+/// A range of memory read or written by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// First address that was accessed.
+    pub address: u16,
+    /// Number of bytes that were accessed.
+    pub len: u16,
+}
+
+impl MemoryAccess {
+    fn at_index(state: &Chip8State, len: usize) -> Self {
+        Self {
+            address: state.index_register,
+            len: len as u16,
+        }
+    }
+
+    /// Whether `address` is one of the accessed bytes.
+    pub fn contains(&self, address: u16) -> bool {
+        (self.address as u32..self.address as u32 + self.len as u32).contains(&(address as u32))
+    }
+}
+
+/// What happened while executing a single instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StepResult {
+    /// Address the executed instruction was fetched from.
+    pub address: u16,
+    /// The raw opcode that was executed.
+    pub opcode: u16,
+    /// The decoded instruction, or `None` if nothing was executed.
+    pub instruction: Option<Instruction>,
+    /// Whether the instruction drew a sprite.
+    pub drew: bool,
+    /// Whether the instruction is blocked on `FX0A` waiting for a key press.
+    pub waiting_for_key: bool,
+    /// Whether the sound timer is running after the instruction.
+    pub sound_on: bool,
+    /// Whether the program exited with the SUPER-CHIP `00FD` instruction.
+    pub exited: bool,
+    /// Whether nothing was executed because a sprite was drawn this frame and the
+    /// [`Quirks::display_wait`] quirk is active.
+    pub waiting_for_vblank: bool,
+    /// Memory the instruction read through I, if any.
+    pub memory_read: Option<MemoryAccess>,
+    /// Memory the instruction wrote through I, if any.
+    pub memory_written: Option<MemoryAccess>,
+}
+
+/// Summary of a batch of executed instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunResult {
+    /// Number of instructions executed.
+    pub cycles: u32,
+    /// Whether any of the instructions drew a sprite.
+    pub drew: bool,
+    /// Whether the last instruction is blocked waiting for a key press.
+    pub waiting_for_key: bool,
+    /// Whether the sound timer is running at the end of the batch.
+    pub sound_on: bool,
+    /// Whether the program exited. No further instructions are executed after this.
+    pub exited: bool,
+}
+
+impl RunResult {
+    fn record(&mut self, step: StepResult) {
+        self.cycles += 1;
+        self.drew |= step.drew;
+        self.waiting_for_key = step.waiting_for_key;
+        self.sound_on = step.sound_on;
+        self.exited = step.exited;
+    }
+}
+
+/// Skips the next instruction, which is 4 bytes long for the XO-CHIP `F000 NNNN` long load.
+fn skip_instruction(state: &mut Chip8State) {
+    let pc = state.program_counter as usize;
+    let is_long_load = state.ram.get(pc..pc + 2) == Some(&[0xF0, 0x00]);
+    let length = if is_long_load { 4 } else { 2 };
+    state.program_counter = state.program_counter.wrapping_add(length);
+}
+
+/// Registers Vx up until Vy, in descending order if Vx comes after Vy.
+fn register_range(vx: u8, vy: u8) -> Vec<u8> {
+    if vx <= vy {
+        (vx..=vy).collect()
+    } else {
+        (vy..=vx).rev().collect()
+    }
+}
+
+/// Slowest clock speed supported, one instruction per frame.
+pub const MIN_CLOCK_SPEED: u32 = 60;
+/// Fastest clock speed supported.
+pub const MAX_CLOCK_SPEED: u32 = 100_000_000;
+
+pub struct Chip8Interpreter<
+    D: Chip8Display,
+    K: Chip8Keyboard,
+    B: Chip8Beeper,
+    R: Chip8Random = XorShiftRng,
+    C: Chip8Clock = RealTimeClock,
+> {
     pub max_clock_speed: u32,
+    pub quirks: Quirks,
+    pub state: Chip8State,
+    pub framebuffer: Framebuffer,
     pub display: D,
     pub keyboard: K,
     pub beeper: B,
+    pub clock: C,
+    rng: R,
+    waiting_for_vblank: bool,
+    rewind_buffer: RewindBuffer,
 }
 
-impl<D: Chip8Display, K: Chip8Keyboard, B: Chip8Beeper> Chip8Interpreter<D, K, B> {
-    pub fn new(max_clock_speed: u32, display: D, keyboard: K, beeper: B) -> Self {
+impl<D: Chip8Display, K: Chip8Keyboard, B: Chip8Beeper, R: Chip8Random, C: Chip8Clock>
+    Chip8Interpreter<D, K, B, R, C>
+{
+    pub fn new(max_clock_speed: u32, quirks: Quirks, display: D, keyboard: K, beeper: B) -> Self {
         Self {
             max_clock_speed,
+            quirks,
+            state: Chip8State::new(quirks.memory_size),
+            framebuffer: Framebuffer::new(),
             display,
             keyboard,
             beeper,
+            clock: C::new(),
+            rng: R::new(rand::random()),
+            waiting_for_vblank: false,
+            rewind_buffer: RewindBuffer::new(0),
+        }
+    }
+
+    /// Reseeds the random number generator, to make runs reproducible.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = R::new(seed);
+    }
+
+    /// Resets the machine state and loads `program` at 0x200.
+    pub fn load_program(&mut self, program: &[u8]) -> Chip8Result<()> {
+        self.state = Chip8State::new(self.quirks.memory_size);
+        self.framebuffer = Framebuffer::new();
+        self.waiting_for_vblank = false;
+        self.rewind_buffer.clear();
+        self.beeper
+            .set_pattern(&self.state.audio_pattern, playback_rate(self.state.pitch));
+        self.state.load_program(program)
+    }
+
+    /// Captures everything needed to resume the machine later with [`Self::restore`].
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            quirks: self.quirks,
+            state: self.state.clone(),
+            framebuffer: self.framebuffer.pixels().to_vec(),
+            key_states: self.keyboard.key_states(),
+            rng_state: self.rng.state(),
+            waiting_for_vblank: self.waiting_for_vblank,
+        }
+    }
+
+    /// Puts the machine back in the state captured by [`Self::snapshot`].
+    pub fn restore(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        self.quirks = snapshot.quirks;
+        self.state = snapshot.state.clone();
+        self.rng.set_state(snapshot.rng_state);
+        self.waiting_for_vblank = snapshot.waiting_for_vblank;
+        self.keyboard.set_key_states(snapshot.key_states);
+        self.framebuffer
+            .set_high_resolution(self.state.high_resolution);
+        self.framebuffer.select_planes(self.state.selected_planes);
+        self.framebuffer.set_pixels(&snapshot.framebuffer);
+        self.beeper
+            .set_pattern(&self.state.audio_pattern, playback_rate(self.state.pitch));
+        self.display.present(&self.framebuffer)
+    }
+
+    /// Keeps a snapshot of the last `frames` frames around for [`Self::rewind`]. Rewinding is
+    /// disabled by default.
+    pub fn set_rewind_capacity(&mut self, frames: usize) {
+        self.rewind_buffer = RewindBuffer::new(frames);
+    }
+
+    /// Steps back up to `frames` frames. Returns whether there was any history to go back to.
+    pub fn rewind(&mut self, frames: usize) -> io::Result<bool> {
+        match self.rewind_buffer.rewind(frames) {
+            Some(snapshot) => {
+                self.restore(&snapshot)?;
+                Ok(true)
+            }
+            None => Ok(false),
         }
     }
 
-    pub fn run<P: AsRef<Path>>(self, path: P) -> io::Result<()> {
-        let program = fs::read(path).expect("Could not read file.");
+    /// Number of instructions executed per 60 Hz frame at the configured clock speed.
+    pub fn cycles_per_frame(&self) -> u32 {
+        (self.max_clock_speed / 60).max(1)
+    }
+
+    pub fn run<P: AsRef<Path>>(self, path: P) -> Chip8Result<()> {
+        let program = fs::read(path)?;
         self.run_program(&program)
     }
-    pub fn run_program(mut self, program: &[u8]) -> io::Result<()> {
-        let mut state = Chip8State::default();
 
-        state.load_program(program);
+    /// Loads `program` and runs it until it exits, pacing the CPU and timers against the clock.
+    pub fn run_program(mut self, program: &[u8]) -> Chip8Result<()> {
+        self.load_program(program)?;
 
         let cpu_frame_time_micros = (1_000_000. / self.max_clock_speed as f64) as u64;
-        let mut next_cpu_frame = Instant::now() + Duration::from_micros(cpu_frame_time_micros);
-        let mut timer = Timer::new(Duration::from_secs_f32(1. / 60.));
-
-        let mut rng = thread_rng();
+        let mut next_cpu_frame = self.clock.now() + Duration::from_micros(cpu_frame_time_micros);
+        let mut timer = Timer::new(Duration::from_secs_f32(1. / 60.), self.clock.now());
 
         loop {
-            //fetch
-            let byte_a = state.ram[state.program_counter as usize];
-            let byte_b = state.ram[state.program_counter as usize + 1];
-            state.program_counter += 2;
+            if self.step()?.exited {
+                return Ok(());
+            }
 
-            //decode
-            let nibble_0 = (byte_a & 0xF0) >> 4;
-            let nibble_1 = byte_a & 0x0F;
-            let nibble_2 = (byte_b & 0xF0) >> 4;
-            let nibble_3 = byte_b & 0x0F;
+            if timer.tick(self.clock.now()) {
+                self.tick_timers()?;
+            }
 
-            let address = ((nibble_1 as u16) << 8) | byte_b as u16;
+            let time_left = self.clock.time_until(next_cpu_frame);
+            next_cpu_frame += Duration::from_micros(cpu_frame_time_micros);
 
-            let immediate_value = byte_b;
+            self.keyboard
+                .update_keystates(time_left.as_micros() as u64)?;
+        }
+    }
 
-            match [nibble_0, nibble_1, nibble_2, nibble_3] {
-                //clear display
-                [0x0, 0x0, 0xE, 0x0] => {
-                    self.display.clear()?;
-                }
-                //return
-                [0x0, 0x0, 0xE, 0xE] => {
-                    state.program_counter = state.stack[state.stack_pointer as usize];
-                    state.stack_pointer -= 1;
-                }
-                //jump to address
-                [0x1, _, _, _] => state.program_counter = address,
-                //call subroutine
-                [0x2, _, _, _] => {
-                    state.stack_pointer += 1;
-                    state.stack[state.stack_pointer as usize] = state.program_counter;
-                    state.program_counter = address;
-                }
-                //skip if Vx == NN
-                [0x3, vx, _, _] => {
-                    if state.register(vx) == immediate_value {
-                        state.program_counter += 2;
-                    }
-                }
-                //skip if Vx != NN
-                [0x4, vx, _, _] => {
-                    if state.register(vx) != immediate_value {
-                        state.program_counter += 2;
-                    }
+    /// Runs one 60 Hz frame: polls the keyboard, executes [`Self::cycles_per_frame`]
+    /// instructions and then ticks the timers. No wall-clock pacing is done.
+    pub fn run_frame(&mut self) -> Chip8Result<RunResult> {
+        self.run_paced_frame(Duration::ZERO)
+    }
+
+    /// Runs one frame like [`Self::run_frame`], but first lets the keyboard wait up to `wait`
+    /// for input, to pace frames against wall-clock time. Frontends should run every frame
+    /// through here, so recorded movies replay in step with [`Self::run_frame`].
+    pub fn run_paced_frame(&mut self, wait: Duration) -> Chip8Result<RunResult> {
+        self.keyboard.update_keystates(wait.as_micros() as u64)?;
+        let mut result = self.run_cycles(self.cycles_per_frame())?;
+        result.sound_on = self.tick_timers()?;
+        Ok(result)
+    }
+
+    /// Runs up to `frames` frames with [`Self::run_frame`], stopping early if the program exits.
+    pub fn run_frames(&mut self, frames: u32) -> Chip8Result<RunResult> {
+        let mut result = RunResult::default();
+        for _ in 0..frames {
+            let frame = self.run_frame()?;
+            result.cycles += frame.cycles;
+            result.drew |= frame.drew;
+            result.waiting_for_key = frame.waiting_for_key;
+            result.sound_on = frame.sound_on;
+            result.exited = frame.exited;
+            if result.exited {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Executes up to `cycles` instructions without touching the timers, stopping early if the
+    /// program exits or has to wait for the next vertical blank.
+    pub fn run_cycles(&mut self, cycles: u32) -> Chip8Result<RunResult> {
+        let mut result = RunResult::default();
+        for _ in 0..cycles {
+            let step = self.step()?;
+            if step.waiting_for_vblank {
+                break;
+            }
+            result.record(step);
+            if result.exited {
+                break;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Decrements the delay and sound timers, drives the beeper and presents the frame. This
+    /// marks the end of a frame, so it also records the frame for rewinding if enabled.
+    /// Returns whether sound is playing.
+    pub fn tick_timers(&mut self) -> io::Result<bool> {
+        self.waiting_for_vblank = false;
+        if self.state.delay_timer > 0 {
+            self.state.delay_timer -= 1;
+        }
+        let sound_on = self.state.sound_timer > 0;
+        if sound_on {
+            self.state.sound_timer -= 1;
+            self.beeper.play();
+        } else {
+            self.beeper.pause();
+        }
+        self.display.present(&self.framebuffer)?;
+        if self.rewind_buffer.capacity() > 0 {
+            let snapshot = self.snapshot();
+            self.rewind_buffer.push(&snapshot);
+        }
+        Ok(sound_on)
+    }
+
+    /// Fetches, decodes and executes a single instruction, advancing the clock by one cycle.
+    pub fn step(&mut self) -> Chip8Result<StepResult> {
+        self.clock.advance(self.max_clock_speed);
+        let state = &mut self.state;
+        let quirks = self.quirks;
+
+        //fetch
+        let pc = state.program_counter;
+        if self.waiting_for_vblank {
+            return Ok(StepResult {
+                address: pc,
+                waiting_for_vblank: true,
+                ..Default::default()
+            });
+        }
+        if pc as usize + 1 >= state.ram.len() {
+            return Err(Chip8Error::ProgramCounterOutOfBounds { pc });
+        }
+        let opcode = u16::from_be_bytes([state.ram[pc as usize], state.ram[pc as usize + 1]]);
+
+        //decode
+        let instruction = match Instruction::decode_bytes(&state.ram[pc as usize..]) {
+            Ok(instruction) => instruction,
+            Err(DecodeError::Truncated) => {
+                return Err(Chip8Error::MemoryOutOfBounds {
+                    pc,
+                    opcode,
+                    address: state.ram.len(),
+                })
+            }
+            Err(_) => return Err(Chip8Error::UnknownOpcode { pc, opcode }),
+        };
+        state.program_counter = pc.wrapping_add(instruction.size());
+
+        let mut result = StepResult {
+            address: pc,
+            opcode,
+            instruction: Some(instruction),
+            ..Default::default()
+        };
+
+        let plane_count = state.selected_planes.count_ones() as usize;
+
+        // Checks that `len` bytes starting at I are inside RAM.
+        let check_memory = |state: &Chip8State, len: usize| {
+            let end = state.index_register as usize + len;
+            if end > state.ram.len() {
+                Err(Chip8Error::MemoryOutOfBounds {
+                    pc,
+                    opcode,
+                    address: end - 1,
+                })
+            } else {
+                Ok(())
+            }
+        };
+
+        match instruction {
+            //clear display
+            Instruction::Clear => {
+                self.framebuffer.clear();
+            }
+            //scroll down N pixels
+            Instruction::ScrollDown { rows } => self.framebuffer.scroll_down(rows),
+            //scroll up N pixels
+            Instruction::ScrollUp { rows } => self.framebuffer.scroll_up(rows),
+            //scroll right 4 pixels
+            Instruction::ScrollRight => self.framebuffer.scroll_right(),
+            //scroll left 4 pixels
+            Instruction::ScrollLeft => self.framebuffer.scroll_left(),
+            //exit
+            Instruction::Exit => {
+                state.program_counter = pc;
+                result.exited = true;
+            }
+            //low resolution
+            Instruction::LowResolution => {
+                state.high_resolution = false;
+                self.framebuffer.set_high_resolution(false);
+            }
+            //high resolution
+            Instruction::HighResolution => {
+                state.high_resolution = true;
+                self.framebuffer.set_high_resolution(true);
+            }
+            //return
+            Instruction::Return => {
+                if state.stack_pointer == 0 {
+                    return Err(Chip8Error::StackUnderflow { pc, opcode });
                 }
-                //skip if Vx == Vy
-                [0x5, vx, vy, 0x0] => {
-                    if state.register(vx) == state.register(vy) {
-                        state.program_counter += 2;
-                    }
+                state.program_counter = state.stack[state.stack_pointer as usize];
+                state.stack_pointer -= 1;
+            }
+            //jump to address
+            Instruction::Jump { address } => state.program_counter = address,
+            //call subroutine
+            Instruction::Call { address } => {
+                if state.stack_pointer as usize + 1 >= state.stack.len() {
+                    return Err(Chip8Error::StackOverflow { pc, opcode });
                 }
-                //Vx = value
-                [0x6, vx, _, _] => *state.register_mut(vx) = immediate_value,
-                //Vx += value
-                [0x7, vx, _, _] => {
-                    *state.register_mut(vx) = state.register(vx).wrapping_add(immediate_value)
+                state.stack_pointer += 1;
+                state.stack[state.stack_pointer as usize] = state.program_counter;
+                state.program_counter = address;
+            }
+            //skip if Vx == NN
+            Instruction::SkipIfEqual { x: vx, value } => {
+                if state.register(vx) == value {
+                    skip_instruction(state);
                 }
-                //Vx = Vy
-                [0x8, vx, vy, 0x0] => *state.register_mut(vx) = state.register(vy),
-                //Vx |= Vy
-                [0x8, vx, vy, 0x1] => *state.register_mut(vx) |= state.register(vy),
-                //Vx &= Vy
-                [0x8, vx, vy, 0x2] => *state.register_mut(vx) &= state.register(vy),
-                //Vx ^= Vy
-                [0x8, vx, vy, 0x3] => *state.register_mut(vx) ^= state.register(vy),
-                //Vx += Vy
-                [0x8, vx, vy, 0x4] => {
-                    let (result, overflow) = state.register(vx).overflowing_add(state.register(vy));
-                    *state.register_mut(vx) = result;
-                    state.set_flag(overflow);
+            }
+            //skip if Vx != NN
+            Instruction::SkipIfNotEqual { x: vx, value } => {
+                if state.register(vx) != value {
+                    skip_instruction(state);
                 }
-                //Vx -= Vy
-                [0x8, vx, vy, 0x5] => {
-                    let (result, borrow) = state.register(vx).overflowing_sub(state.register(vy));
-                    *state.register_mut(vx) = result;
-                    state.set_flag(!borrow);
+            }
+            //skip if Vx == Vy
+            Instruction::SkipIfRegistersEqual { x: vx, y: vy } => {
+                if state.register(vx) == state.register(vy) {
+                    skip_instruction(state);
                 }
-                //Vx >>= 1
-                [0x8, vx, _, 0x6] => {
-                    let (result, borrow) = state.register(vx).overflowing_shr(1);
-                    *state.register_mut(vx) = result;
-                    state.set_flag(!borrow);
+            }
+            //store Vx up until Vy
+            Instruction::StoreRange { x: vx, y: vy } => {
+                let registers = register_range(vx, vy);
+                check_memory(state, registers.len())?;
+                result.memory_written = Some(MemoryAccess::at_index(state, registers.len()));
+                for (offset, register) in registers.into_iter().enumerate() {
+                    state.ram[state.index_register as usize + offset] = state.register(register);
                 }
-                //Vx = Vy - Vx
-                [0x8, vx, vy, 0x7] => {
-                    let (result, borrow) = state.register(vy).overflowing_sub(state.register(vx));
-                    *state.register_mut(vx) = result;
-                    state.set_flag(!borrow);
+            }
+            //load Vx up until Vy
+            Instruction::LoadRange { x: vx, y: vy } => {
+                let registers = register_range(vx, vy);
+                check_memory(state, registers.len())?;
+                result.memory_read = Some(MemoryAccess::at_index(state, registers.len()));
+                for (offset, register) in registers.into_iter().enumerate() {
+                    *state.register_mut(register) =
+                        state.ram[state.index_register as usize + offset];
                 }
-                //Vx <<= 1
-                [0x8, vx, _, 0xE] => {
-                    let (result, borrow) = state.register(vx).overflowing_shl(1);
-                    *state.register_mut(vx) = result;
-                    state.set_flag(!borrow);
+            }
+            //Vx = value
+            Instruction::SetImmediate { x: vx, value } => *state.register_mut(vx) = value,
+            //Vx += value
+            Instruction::AddImmediate { x: vx, value } => {
+                *state.register_mut(vx) = state.register(vx).wrapping_add(value)
+            }
+            //Vx = Vy
+            Instruction::Set { x: vx, y: vy } => *state.register_mut(vx) = state.register(vy),
+            //Vx |= Vy
+            Instruction::Or { x: vx, y: vy } => {
+                *state.register_mut(vx) |= state.register(vy);
+                if quirks.logic_resets_flag {
+                    state.set_flag(false);
                 }
-                // Skip if Vx != Vy
-                [0x9, vx, vy, 0x0] => {
-                    if state.register(vx) != state.register(vy) {
-                        state.program_counter += 2;
-                    }
+            }
+            //Vx &= Vy
+            Instruction::And { x: vx, y: vy } => {
+                *state.register_mut(vx) &= state.register(vy);
+                if quirks.logic_resets_flag {
+                    state.set_flag(false);
                 }
-                //I = address
-                [0xA, _, _, _] => state.index_register = address,
-                // Jump to NNN + v0
-                [0xB, _, _, _] => state.program_counter = state.register(0x0) as u16 + address,
-                // Vx = rand() & NN
-                [0xC, vx, _, _] => *state.register_mut(vx) = immediate_value & rng.gen::<u8>(),
-                //Display sprite
-                [0xD, vx, vy, _] => {
-                    let vx = state.register(vx);
-                    let vy = state.register(vy);
-                    let data = &state.ram[state.index_register as usize
-                        ..state.index_register as usize + nibble_3 as usize];
-
-                    let flag = self.display.draw(vx, vy, data)?;
-
-                    state.set_flag(flag);
+            }
+            //Vx ^= Vy
+            Instruction::Xor { x: vx, y: vy } => {
+                *state.register_mut(vx) ^= state.register(vy);
+                if quirks.logic_resets_flag {
+                    state.set_flag(false);
                 }
-                // skip if key()
-                [0xE, vx, 0x9, 0xE] => {
-                    if self.keyboard.is_key_down(state.register(vx)) {
-                        state.program_counter += 2;
-                    }
+            }
+            //Vx += Vy
+            Instruction::Add { x: vx, y: vy } => {
+                let (result, overflow) = state.register(vx).overflowing_add(state.register(vy));
+                *state.register_mut(vx) = result;
+                state.set_flag(overflow);
+            }
+            //Vx -= Vy
+            Instruction::Subtract { x: vx, y: vy } => {
+                let (result, borrow) = state.register(vx).overflowing_sub(state.register(vy));
+                *state.register_mut(vx) = result;
+                state.set_flag(!borrow);
+            }
+            //Vx >>= 1
+            Instruction::ShiftRight { x: vx, y: vy } => {
+                let value = state.register(if quirks.shift_uses_vy { vy } else { vx });
+                *state.register_mut(vx) = value >> 1;
+                state.set_flag(value & 0x01 != 0);
+            }
+            //Vx = Vy - Vx
+            Instruction::SubtractReversed { x: vx, y: vy } => {
+                let (result, borrow) = state.register(vy).overflowing_sub(state.register(vx));
+                *state.register_mut(vx) = result;
+                state.set_flag(!borrow);
+            }
+            //Vx <<= 1
+            Instruction::ShiftLeft { x: vx, y: vy } => {
+                let value = state.register(if quirks.shift_uses_vy { vy } else { vx });
+                *state.register_mut(vx) = value << 1;
+                state.set_flag(value & 0x80 != 0);
+            }
+            // Skip if Vx != Vy
+            Instruction::SkipIfRegistersNotEqual { x: vx, y: vy } => {
+                if state.register(vx) != state.register(vy) {
+                    skip_instruction(state);
                 }
-                // skip if !key()
-                [0xE, vx, 0xA, 0x1] => {
-                    if !self.keyboard.is_key_down(state.register(vx)) {
-                        state.program_counter += 2;
-                    }
+            }
+            //I = address
+            Instruction::SetIndex { address } => state.index_register = address,
+            // Jump to NNN + V0, or XNN + Vx
+            Instruction::JumpOffset { address } => {
+                let vx = (address >> 8) as u8;
+                let offset = state.register(if quirks.jump_uses_vx { vx } else { 0x0 });
+                state.program_counter = offset as u16 + address;
+            }
+            // Vx = rand() & NN
+            Instruction::Random { x: vx, value } => {
+                *state.register_mut(vx) = value & self.rng.next_byte(&state.ram)
+            }
+            //Display 16x16 sprite
+            Instruction::Draw {
+                x: vx,
+                y: vy,
+                height: 0,
+            } => {
+                check_memory(state, 32 * plane_count)?;
+                result.memory_read = Some(MemoryAccess::at_index(state, 32 * plane_count));
+                let vx = state.register(vx);
+                let vy = state.register(vy);
+                let data = &state.ram[state.index_register as usize..][..32 * plane_count];
+
+                let flag = self
+                    .framebuffer
+                    .draw_large(vx, vy, data, quirks.wrap_sprites);
+
+                state.set_flag(flag);
+                result.drew = true;
+                self.waiting_for_vblank = quirks.display_wait;
+            }
+            //Display sprite
+            Instruction::Draw {
+                x: vx,
+                y: vy,
+                height,
+            } => {
+                let len = height as usize * plane_count;
+                check_memory(state, len)?;
+                result.memory_read = Some(MemoryAccess::at_index(state, len));
+                let vx = state.register(vx);
+                let vy = state.register(vy);
+                let data = &state.ram[state.index_register as usize..][..len];
+
+                let flag = self.framebuffer.draw(vx, vy, data, quirks.wrap_sprites);
+
+                state.set_flag(flag);
+                result.drew = true;
+                self.waiting_for_vblank = quirks.display_wait;
+            }
+            // skip if key()
+            Instruction::SkipIfKey { x: vx } => {
+                if self.keyboard.is_key_down(state.register(vx)) {
+                    skip_instruction(state);
                 }
-                // Vx = delay timer
-                [0xF, vx, 0x0, 0x7] => {
-                    *state.register_mut(vx) = state.delay_timer;
+            }
+            // skip if !key()
+            Instruction::SkipIfNotKey { x: vx } => {
+                if !self.keyboard.is_key_down(state.register(vx)) {
+                    skip_instruction(state);
                 }
-                // Vx = get_key()
-                [0xF, vx, 0x0, 0xA] => {
-                    if let Some(last_key) = self.keyboard.last_key_pressed() {
-                        *state.register_mut(vx) = last_key;
-                    } else {
-                        state.program_counter -= 2;
-                    }
+            }
+            // I = NNNN, read from the next two bytes
+            Instruction::SetIndexLong { address } => state.index_register = address,
+            // Select drawing planes
+            Instruction::SelectPlanes { planes } => {
+                state.selected_planes = planes;
+                self.framebuffer.select_planes(planes);
+            }
+            // Load the audio pattern from I
+            Instruction::LoadAudioPattern => {
+                check_memory(state, 16)?;
+                result.memory_read = Some(MemoryAccess::at_index(state, 16));
+                let start = state.index_register as usize;
+                state
+                    .audio_pattern
+                    .copy_from_slice(&state.ram[start..start + 16]);
+                self.beeper
+                    .set_pattern(&state.audio_pattern, playback_rate(state.pitch));
+            }
+            // Vx = delay timer
+            Instruction::GetDelay { x: vx } => {
+                *state.register_mut(vx) = state.delay_timer;
+            }
+            // Vx = get_key()
+            Instruction::WaitKey { x: vx } => {
+                if let Some(last_key) = self.keyboard.last_key_pressed() {
+                    *state.register_mut(vx) = last_key;
+                } else {
+                    state.program_counter = pc;
+                    result.waiting_for_key = true;
                 }
-                // Set delay timer to vx
-                [0xF, vx, 0x1, 0x5] => {
-                    state.delay_timer = state.register(vx);
+            }
+            // Set delay timer to vx
+            Instruction::SetDelay { x: vx } => {
+                state.delay_timer = state.register(vx);
+            }
+            // Set sound timer to vx
+            Instruction::SetSound { x: vx } => {
+                state.sound_timer = state.register(vx);
+            }
+            // I += Vx
+            Instruction::AddIndex { x: vx } => {
+                let result = state.index_register.wrapping_add(state.register(vx) as u16);
+                state.index_register = result;
+                if quirks.index_overflow_sets_flag {
+                    state.set_flag(result > 0xFFF);
                 }
-                // Set sound timer to vx
-                [0xF, vx, 0x1, 0x8] => {
-                    state.sound_timer = state.register(vx);
+            }
+            // I = Vx'th character index
+            Instruction::Font { x: vx } => {
+                state.index_register = state.register(vx) as u16 * 5;
+            }
+            // I = Vx'th large character index
+            Instruction::BigFont { x: vx } => {
+                state.index_register = BIG_FONT_ADDRESS + state.register(vx) as u16 * 10;
+            }
+            // Set the audio pitch to Vx
+            Instruction::SetPitch { x: vx } => {
+                state.pitch = state.register(vx);
+                self.beeper
+                    .set_pattern(&state.audio_pattern, playback_rate(state.pitch));
+            }
+            // Convert and store Vx to decimal
+            Instruction::Bcd { x: vx } => {
+                check_memory(state, 3)?;
+                result.memory_written = Some(MemoryAccess::at_index(state, 3));
+                let value = state.register(vx);
+                state.ram[state.index_register as usize] = value / 100;
+                state.ram[state.index_register as usize + 1] = value / 10 % 10;
+                state.ram[state.index_register as usize + 2] = value % 10;
+            }
+            // Store everything up until Vx
+            Instruction::Store { x: vx } => {
+                check_memory(state, vx as usize + 1)?;
+                result.memory_written = Some(MemoryAccess::at_index(state, vx as usize + 1));
+                for i in 0..=vx {
+                    state.ram[(state.index_register + i as u16) as usize] = state.register(i);
                 }
-                // I += Vx
-                [0xF, vx, 0x1, 0xE] => {
-                    let (result, overflow) = state
+                if quirks.load_store_increments_index {
+                    state.index_register = state
                         .index_register
-                        .overflowing_add(state.register(vx) as u16);
-                    state.index_register = result;
-                    state.set_flag(overflow);
-                }
-                // I = Vx'th character index
-                [0xF, vx, 0x2, 0x9] => {
-                    state.index_register = state.register(vx) as u16 * 5;
-                }
-                // Convert and store Vx to decimal
-                [0xF, vx, 0x3, 0x3] => {
-                    let value = state.register(vx);
-                    state.ram[state.index_register as usize] = value / 100;
-                    state.ram[state.index_register as usize + 1] = value / 10 % 10;
-                    state.ram[state.index_register as usize + 2] = value % 10;
-                }
-                // Store everything up until Vx
-                [0xF, vx, 0x5, 0x5] => {
-                    for i in 0..=vx {
-                        state.ram[(state.index_register + i as u16) as usize] = state.register(i);
-                    }
-                }
-                // Load everything up until Vx
-                [0xF, vx, 0x6, 0x5] => {
-                    for i in 0..=vx {
-                        *state.register_mut(i) =
-                            state.ram[(state.index_register + i as u16) as usize];
-                    }
-                }
-                _ => {
-                    self.display.clear()?;
-                    self.display.flush()?;
-                    panic!(
-                        "Unknown instruction {:01x}{:01x}{:01x}{:01x}",
-                        nibble_0, nibble_1, nibble_2, nibble_3
-                    )
+                        .wrapping_add(vx as u16 + !quirks.index_skips_last_register as u16);
                 }
             }
-
-            if timer.tick() {
-                if state.delay_timer > 0 {
-                    state.delay_timer -= 1;
+            // Load everything up until Vx
+            Instruction::Load { x: vx } => {
+                check_memory(state, vx as usize + 1)?;
+                result.memory_read = Some(MemoryAccess::at_index(state, vx as usize + 1));
+                for i in 0..=vx {
+                    *state.register_mut(i) = state.ram[(state.index_register + i as u16) as usize];
                 }
-                if state.sound_timer > 0 {
-                    state.sound_timer -= 1;
-                    self.beeper.play();
-                } else {
-                    self.beeper.pause();
+                if quirks.load_store_increments_index {
+                    state.index_register = state
+                        .index_register
+                        .wrapping_add(vx as u16 + !quirks.index_skips_last_register as u16);
                 }
-                self.display.flush()?;
             }
-
-            let now = Instant::now();
-
-            let time_left = next_cpu_frame - now;
-
-            let time_left = time_left.max(Duration::ZERO);
-            next_cpu_frame += Duration::from_micros(cpu_frame_time_micros);
-
-            self.keyboard
-                .update_keystates(time_left.as_micros() as u64)?;
+            // Store V0 up until Vx in the RPL user flags
+            Instruction::StoreFlags { x: vx } => {
+                state.rpl_flags[..=vx as usize]
+                    .copy_from_slice(&state.data_registers[..=vx as usize]);
+            }
+            // Load V0 up until Vx from the RPL user flags
+            Instruction::LoadFlags { x: vx } => {
+                state.data_registers[..=vx as usize]
+                    .copy_from_slice(&state.rpl_flags[..=vx as usize]);
+            }
         }
+
+        result.sound_on = state.sound_timer > 0;
+        Ok(result)
     }
 }
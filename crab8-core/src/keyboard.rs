@@ -5,4 +5,7 @@ pub trait Chip8Keyboard {
     fn update_keystates(&mut self, max_duration_microseconds: u64) -> io::Result<()>;
     fn is_key_down(&self, key: u8) -> bool;
     fn last_key_pressed(&self) -> Option<u8>;
+    /// Bitmask of the keys that are held down, bit N for key N.
+    fn key_states(&self) -> u16;
+    fn set_key_states(&mut self, key_states: u16);
 }
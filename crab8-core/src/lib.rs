@@ -1,11 +1,42 @@
+mod assembler;
 mod beeper;
+mod binary;
+mod clock;
+mod disassembler;
 mod display;
+mod error;
+mod framebuffer;
+mod headless;
+mod instruction;
 mod interpreter;
 mod keyboard;
+mod movie;
+mod quirks;
+mod random;
+mod rewind;
+mod snapshot;
 mod state;
+mod tone;
+mod wav;
 
-pub use beeper::Chip8Beeper;
+pub use assembler::{assemble, Assembly, AssemblyError};
+pub use beeper::{playback_rate, Chip8Beeper, Waveform};
+pub use clock::{Chip8Clock, RealTimeClock, VirtualClock};
+pub use disassembler::Disassembly;
 pub use display::Chip8Display;
-pub use interpreter::Chip8Interpreter;
+pub use error::{Chip8Error, Chip8Result};
+pub use framebuffer::{Framebuffer, MAX_HEIGHT, MAX_WIDTH};
+pub use headless::{HeadlessDisplay, HeadlessInterpreter, NullBeeper, ScriptedKeyboard};
+pub use instruction::{DecodeError, Instruction};
+pub use interpreter::{
+    Chip8Interpreter, MemoryAccess, RunResult, StepResult, MAX_CLOCK_SPEED, MIN_CLOCK_SPEED,
+};
 pub use keyboard::Chip8Keyboard;
-pub use state::Chip8State;
+pub use movie::{Movie, MovieEvent, MovieKeyboard};
+pub use quirks::Quirks;
+pub use random::{Chip8Random, ProgramPageRng, RandomKind, XorShiftRng};
+pub use rewind::RewindBuffer;
+pub use snapshot::Snapshot;
+pub use state::{Chip8State, CHIP8_MEMORY_SIZE, PROGRAM_START, XO_CHIP_MEMORY_SIZE};
+pub use tone::Tone;
+pub use wav::WavRecorder;
@@ -0,0 +1,443 @@
+use std::io;
+
+use crate::{
+    binary::{Reader, Writer},
+    Chip8Beeper, Chip8Clock, Chip8Display, Chip8Error, Chip8Interpreter, Chip8Keyboard,
+    Chip8Random, Chip8Result, Quirks, RandomKind, MAX_CLOCK_SPEED, MIN_CLOCK_SPEED,
+};
+
+/// Identifies a crab8 movie file.
+const MAGIC: &[u8; 8] = b"CRAB8MOV";
+
+/// 64-bit FNV-1a, used to recognise ROMs and machine states in movies.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// The keypad changing state at the start of a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieEvent {
+    /// The frame the change happens in, counting from 0.
+    pub frame: u64,
+    /// Keys held down from this frame on, bit N for key N.
+    pub key_states: u16,
+}
+
+/// A recording of the keypad input of a run, which replays to the exact same run.
+///
+/// Besides the input, a movie stores everything else that affects a run: the ROM, the quirks,
+/// the random number generator and its seed, and the clock speed. Movies use the same kind of
+/// versioned big-endian binary format as [`crate::Snapshot`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// Hash of the ROM the movie was recorded with.
+    pub rom_hash: u64,
+    pub quirks: Quirks,
+    pub rng: RandomKind,
+    pub seed: u64,
+    pub clock_speed: u32,
+    /// Length of the movie in frames.
+    pub frames: u64,
+    /// Keypad changes, ordered by frame.
+    pub events: Vec<MovieEvent>,
+    /// Hash of the snapshot taken at the end of the recording, to check playback against.
+    pub final_state_hash: Option<u64>,
+}
+
+impl Movie {
+    /// Version of the format written by [`Self::to_bytes`].
+    pub const VERSION: u16 = 2;
+
+    /// Starts an empty movie for a run of `program`.
+    pub fn new(
+        program: &[u8],
+        quirks: Quirks,
+        rng: RandomKind,
+        seed: u64,
+        clock_speed: u32,
+    ) -> Self {
+        Self {
+            rom_hash: hash(program),
+            quirks,
+            rng,
+            seed,
+            clock_speed,
+            frames: 0,
+            events: Vec::new(),
+            final_state_hash: None,
+        }
+    }
+
+    /// Whether the movie was recorded with `program`.
+    pub fn matches_rom(&self, program: &[u8]) -> bool {
+        self.rom_hash == hash(program)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes(MAGIC);
+        writer.u16(Self::VERSION);
+        writer.u64(self.rom_hash);
+        writer.quirks(&self.quirks);
+        writer.u8(match self.rng {
+            RandomKind::XorShift => 0,
+            RandomKind::ProgramPage => 1,
+        });
+        writer.u64(self.seed);
+        writer.u32(self.clock_speed);
+        writer.u64(self.frames);
+        writer.u8(self.final_state_hash.is_some() as u8);
+        writer.u64(self.final_state_hash.unwrap_or_default());
+        writer.u32(self.events.len() as u32);
+        for event in &self.events {
+            writer.u64(event.frame);
+            writer.u16(event.key_states);
+        }
+        writer.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Chip8Result<Self> {
+        let mut reader = Reader { bytes, invalid };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a crab8 movie"));
+        }
+        let version = reader.u16()?;
+        if version == 0 || version > Self::VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let rom_hash = reader.u64()?;
+        let quirks = reader.quirks()?;
+        // Version 1 didn't record the generator, and only had xorshift.
+        let rng = match version {
+            1 => RandomKind::XorShift,
+            _ => match reader.u8()? {
+                0 => RandomKind::XorShift,
+                1 => RandomKind::ProgramPage,
+                _ => return Err(invalid("unknown random number generator")),
+            },
+        };
+        let seed = reader.u64()?;
+        let clock_speed = reader.u32()?;
+        if !(MIN_CLOCK_SPEED..=MAX_CLOCK_SPEED).contains(&clock_speed) {
+            return Err(invalid("unsupported clock speed"));
+        }
+        let frames = reader.u64()?;
+        let has_final_state_hash = reader.u8()? != 0;
+        let final_state_hash = reader.u64()?;
+        let event_count = reader.u32()?;
+        let mut events: Vec<MovieEvent> = Vec::new();
+        for _ in 0..event_count {
+            let event = MovieEvent {
+                frame: reader.u64()?,
+                key_states: reader.u16()?,
+            };
+            if events.last().is_some_and(|last| last.frame >= event.frame) {
+                return Err(invalid("events out of order"));
+            }
+            events.push(event);
+        }
+        reader.finish()?;
+
+        Ok(Self {
+            rom_hash,
+            quirks,
+            rng,
+            seed,
+            clock_speed,
+            frames,
+            events,
+            final_state_hash: has_final_state_hash.then_some(final_state_hash),
+        })
+    }
+}
+
+fn invalid(reason: &'static str) -> Chip8Error {
+    Chip8Error::InvalidMovie { reason }
+}
+
+enum MovieMode {
+    Off,
+    Recording(Movie),
+    Playing { movie: Movie, next_event: usize },
+}
+
+/// A keyboard that records the input of another keyboard into a [`Movie`], or replays a movie
+/// instead.
+///
+/// Like [`crate::ScriptedKeyboard`], every call to `update_keystates` is one frame. The inner
+/// keyboard is still polled while playing back, so frame pacing and hotkeys keep working.
+/// Restoring a snapshot in the middle of a recording or playback makes the movie go out of
+/// sync.
+pub struct MovieKeyboard<K: Chip8Keyboard> {
+    pub inner: K,
+    frame: u64,
+    key_states: u16,
+    last_key_pressed: Option<u8>,
+    mode: MovieMode,
+}
+
+impl<K: Chip8Keyboard> MovieKeyboard<K> {
+    /// Starts recording into `movie`, from frame 0.
+    pub fn record(&mut self, movie: Movie) {
+        self.frame = 0;
+        self.key_states = 0;
+        self.mode = MovieMode::Recording(movie);
+    }
+
+    /// Stops recording and returns the movie, or `None` if nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        match std::mem::replace(&mut self.mode, MovieMode::Off) {
+            MovieMode::Recording(mut movie) => {
+                movie.frames = self.frame;
+                Some(movie)
+            }
+            mode => {
+                self.mode = mode;
+                None
+            }
+        }
+    }
+
+    /// Starts replaying `movie`, from frame 0.
+    pub fn play(&mut self, movie: Movie) {
+        self.frame = 0;
+        self.key_states = 0;
+        self.mode = MovieMode::Playing {
+            movie,
+            next_event: 0,
+        };
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, MovieMode::Recording(_))
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, MovieMode::Playing { .. })
+    }
+
+    /// Whether all frames of the movie being played back have been played.
+    pub fn playback_finished(&self) -> bool {
+        match &self.mode {
+            MovieMode::Playing { movie, .. } => self.frame >= movie.frames,
+            _ => false,
+        }
+    }
+
+    /// Number of frames recorded or played back so far.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// The movie being recorded or played back.
+    pub fn movie(&self) -> Option<&Movie> {
+        match &self.mode {
+            MovieMode::Off => None,
+            MovieMode::Recording(movie) | MovieMode::Playing { movie, .. } => Some(movie),
+        }
+    }
+
+    /// Switches to `key_states`, pressing the lowest key that wasn't held down yet.
+    fn change_key_states(&mut self, key_states: u16) {
+        let pressed = key_states & !self.key_states;
+        self.last_key_pressed = (pressed != 0).then(|| pressed.trailing_zeros() as u8);
+        self.key_states = key_states;
+    }
+}
+
+impl<K: Chip8Keyboard> Chip8Keyboard for MovieKeyboard<K> {
+    fn new() -> Self {
+        Self {
+            inner: K::new(),
+            frame: 0,
+            key_states: 0,
+            last_key_pressed: None,
+            mode: MovieMode::Off,
+        }
+    }
+
+    fn update_keystates(&mut self, max_duration_microseconds: u64) -> io::Result<()> {
+        self.inner.update_keystates(max_duration_microseconds)?;
+        match &mut self.mode {
+            MovieMode::Off => {
+                self.key_states = self.inner.key_states();
+                self.last_key_pressed = self.inner.last_key_pressed();
+            }
+            MovieMode::Recording(movie) => {
+                let key_states = self.inner.key_states();
+                if key_states != self.key_states {
+                    movie.events.push(MovieEvent {
+                        frame: self.frame,
+                        key_states,
+                    });
+                }
+                self.change_key_states(key_states);
+            }
+            MovieMode::Playing { movie, next_event } => {
+                let mut key_states = self.key_states;
+                while let Some(event) = movie.events.get(*next_event) {
+                    if event.frame > self.frame {
+                        break;
+                    }
+                    key_states = event.key_states;
+                    *next_event += 1;
+                }
+                self.change_key_states(key_states);
+            }
+        }
+        self.frame += 1;
+        Ok(())
+    }
+
+    fn is_key_down(&self, key: u8) -> bool {
+        key < 16 && self.key_states & (1 << key) != 0
+    }
+
+    fn last_key_pressed(&self) -> Option<u8> {
+        self.last_key_pressed
+    }
+
+    fn key_states(&self) -> u16 {
+        self.key_states
+    }
+
+    fn set_key_states(&mut self, key_states: u16) {
+        self.key_states = key_states;
+        self.inner.set_key_states(key_states);
+    }
+}
+
+impl<D: Chip8Display, K: Chip8Keyboard, B: Chip8Beeper, R: Chip8Random, C: Chip8Clock>
+    Chip8Interpreter<D, MovieKeyboard<K>, B, R, C>
+{
+    /// Loads `program` and starts recording a movie of it, seeding the random number generator
+    /// with `seed`.
+    pub fn start_recording(&mut self, program: &[u8], seed: u64) -> Chip8Result<()> {
+        self.seed_rng(seed);
+        self.load_program(program)?;
+        let movie = Movie::new(program, self.quirks, R::KIND, seed, self.max_clock_speed);
+        self.keyboard.record(movie);
+        Ok(())
+    }
+
+    /// Stops recording and returns the movie, including the hash of the current state.
+    pub fn finish_recording(&mut self) -> Option<Movie> {
+        let mut movie = self.keyboard.stop_recording()?;
+        movie.final_state_hash = Some(self.state_hash());
+        Some(movie)
+    }
+
+    /// Loads `program` with the settings `movie` was recorded with and starts playing it back.
+    pub fn start_playback(&mut self, program: &[u8], movie: Movie) -> Chip8Result<()> {
+        if !movie.matches_rom(program) {
+            return Err(invalid("recorded with a different ROM"));
+        }
+        if movie.rng != R::KIND {
+            return Err(invalid("recorded with a different random number generator"));
+        }
+        self.quirks = movie.quirks;
+        self.max_clock_speed = movie.clock_speed;
+        self.seed_rng(movie.seed);
+        self.load_program(program)?;
+        self.keyboard.play(movie);
+        Ok(())
+    }
+
+    /// Whether the machine ended up in the state the movie being played back was recorded to
+    /// end in, or `None` if that isn't known.
+    pub fn playback_in_sync(&self) -> Option<bool> {
+        let final_state_hash = self.keyboard.movie()?.final_state_hash?;
+        Some(final_state_hash == self.state_hash())
+    }
+
+    fn state_hash(&self) -> u64 {
+        hash(&self.snapshot().to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        HeadlessDisplay, NullBeeper, ScriptedKeyboard, VirtualClock, XorShiftRng,
+        XO_CHIP_MEMORY_SIZE,
+    };
+
+    #[test]
+    fn movies_round_trip() {
+        let mut movie = Movie::new(
+            &[0x12, 0x00],
+            Quirks::XO_CHIP,
+            RandomKind::ProgramPage,
+            7,
+            1000,
+        );
+        movie.frames = 100;
+        movie.events = vec![
+            MovieEvent {
+                frame: 3,
+                key_states: 0x20,
+            },
+            MovieEvent {
+                frame: 9,
+                key_states: 0,
+            },
+        ];
+        movie.final_state_hash = Some(42);
+        let read = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(read.to_bytes(), movie.to_bytes());
+        assert!(read.matches_rom(&[0x12, 0x00]));
+    }
+
+    #[test]
+    fn unusable_settings_are_rejected() {
+        let mut quirks = Quirks::COSMAC_VIP;
+        for memory_size in [0, 0x1FF, XO_CHIP_MEMORY_SIZE + 1] {
+            quirks.memory_size = memory_size;
+            let movie = Movie::new(&[], quirks, RandomKind::XorShift, 0, 700);
+            assert!(Movie::from_bytes(&movie.to_bytes()).is_err());
+        }
+        for clock_speed in [0, MAX_CLOCK_SPEED + 1] {
+            let movie = Movie::new(
+                &[],
+                Quirks::COSMAC_VIP,
+                RandomKind::XorShift,
+                0,
+                clock_speed,
+            );
+            assert!(Movie::from_bytes(&movie.to_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn version_1_movies_used_xorshift() {
+        let movie = Movie::new(&[], Quirks::COSMAC_VIP, RandomKind::XorShift, 3, 700);
+        let mut bytes = movie.to_bytes();
+        // Version 1 had no generator after the quirks.
+        bytes[8..10].copy_from_slice(&1u16.to_be_bytes());
+        bytes.remove(23);
+        assert_eq!(Movie::from_bytes(&bytes).unwrap(), movie);
+    }
+
+    #[test]
+    fn movies_play_back_with_their_generator() {
+        let movie = Movie::new(
+            &[0x12, 0x00],
+            Quirks::COSMAC_VIP,
+            RandomKind::ProgramPage,
+            0,
+            700,
+        );
+        let mut interpreter: Chip8Interpreter<_, _, _, XorShiftRng, VirtualClock> =
+            Chip8Interpreter::new(
+                700,
+                Quirks::COSMAC_VIP,
+                HeadlessDisplay::new(),
+                MovieKeyboard::<ScriptedKeyboard>::new(),
+                NullBeeper::new(0.),
+            );
+        assert!(interpreter.start_playback(&[0x12, 0x00], movie).is_err());
+    }
+}
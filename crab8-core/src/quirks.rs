@@ -0,0 +1,82 @@
+use crate::{CHIP8_MEMORY_SIZE, XO_CHIP_MEMORY_SIZE};
+
+/// Behavioural differences between CHIP-8 implementations that ROMs rely on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift Vy and store the result in Vx, instead of shifting Vx in place.
+    pub shift_uses_vy: bool,
+    /// `FX55`/`FX65` leave I pointing right after the last register that was stored or loaded.
+    pub load_store_increments_index: bool,
+    /// Along with `load_store_increments_index`, I is left pointing at the last register
+    /// instead of after it, as CHIP-48 adds X to I rather than X + 1.
+    pub index_skips_last_register: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0.
+    pub logic_resets_flag: bool,
+    /// `BNNN` behaves as `BXNN`, jumping to XNN + Vx instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// Drawing a sprite waits for the next vertical blank, so at most one sprite is drawn per
+    /// frame.
+    pub display_wait: bool,
+    /// `FX1E` sets VF when I is pushed past the 12-bit address space.
+    pub index_overflow_sets_flag: bool,
+    /// Sprites crossing the edge of the screen wrap around to the other side instead of being
+    /// clipped.
+    pub wrap_sprites: bool,
+    /// Size of RAM in bytes.
+    pub memory_size: usize,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_index: true,
+        index_skips_last_register: false,
+        logic_resets_flag: true,
+        jump_uses_vx: false,
+        display_wait: true,
+        index_overflow_sets_flag: false,
+        wrap_sprites: false,
+        memory_size: CHIP8_MEMORY_SIZE,
+    };
+
+    /// CHIP-48 on the HP-48 calculators. Unlike SUPER-CHIP, `FX55`/`FX65` still move I, but
+    /// one register short.
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: true,
+        index_skips_last_register: true,
+        logic_resets_flag: false,
+        jump_uses_vx: true,
+        display_wait: false,
+        index_overflow_sets_flag: false,
+        wrap_sprites: false,
+        memory_size: CHIP8_MEMORY_SIZE,
+    };
+
+    /// SUPER-CHIP 1.1 as implemented by modern interpreters, without the vertical blank wait.
+    pub const SCHIP_MODERN: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_index: false,
+        index_skips_last_register: false,
+        logic_resets_flag: false,
+        jump_uses_vx: true,
+        display_wait: false,
+        index_overflow_sets_flag: false,
+        wrap_sprites: false,
+        memory_size: CHIP8_MEMORY_SIZE,
+    };
+
+    /// XO-CHIP as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_index: true,
+        index_skips_last_register: false,
+        logic_resets_flag: false,
+        jump_uses_vx: false,
+        display_wait: false,
+        index_overflow_sets_flag: false,
+        wrap_sprites: true,
+        memory_size: XO_CHIP_MEMORY_SIZE,
+    };
+}
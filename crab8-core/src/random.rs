@@ -0,0 +1,99 @@
+use crate::PROGRAM_START;
+
+/// The source of random numbers for `CXNN`.
+///
+/// The whole state of a generator fits in a `u64`, so it can be stored in save states and runs
+/// can be reproduced by seeding it.
+pub trait Chip8Random {
+    /// Which generator this is, recorded in movies.
+    const KIND: RandomKind;
+    fn new(seed: u64) -> Self;
+    /// The next random byte. `memory` is the machine's RAM, for generators that derive their
+    /// numbers from it like the COSMAC VIP did.
+    fn next_byte(&mut self, memory: &[u8]) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+/// The generators crab8 has, to tell which one a movie was recorded with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RandomKind {
+    #[default]
+    XorShift,
+    ProgramPage,
+}
+
+/// A small, deterministic xorshift generator. This is the default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XorShiftRng {
+    pub state: u64,
+}
+
+impl Chip8Random for XorShiftRng {
+    const KIND: RandomKind = RandomKind::XorShift;
+
+    fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+
+    fn next_byte(&mut self, _memory: &[u8]) -> u8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        (x >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        // Xorshift gets stuck on 0, so swap it for an arbitrary non-zero seed.
+        self.state = if state == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            state
+        };
+    }
+}
+
+/// A generator that mixes the first page of the program into its numbers.
+///
+/// It keeps a 16-bit value like the R9 register of the COSMAC VIP interpreter. Every `CXNN`
+/// advances it, adds the byte its low half points at within the page at 0x200 to its high half,
+/// and uses the high half as the random number. The VIP indexed its own interpreter code instead,
+/// which crab8 doesn't have, so this doesn't reproduce the VIP's sequence. Its numbers are far
+/// from uniform in a similar way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgramPageRng {
+    pub r9: u16,
+}
+
+impl Chip8Random for ProgramPageRng {
+    const KIND: RandomKind = RandomKind::ProgramPage;
+
+    fn new(seed: u64) -> Self {
+        Self { r9: seed as u16 }
+    }
+
+    fn next_byte(&mut self, memory: &[u8]) -> u8 {
+        self.r9 = self.r9.wrapping_add(1);
+        let [high, low] = self.r9.to_be_bytes();
+        let address = PROGRAM_START as usize + low as usize;
+        let high = high.wrapping_add(memory.get(address).copied().unwrap_or_default());
+        self.r9 = u16::from_be_bytes([high, low]);
+        high
+    }
+
+    fn state(&self) -> u64 {
+        self.r9 as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.r9 = state as u16;
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+use crate::Snapshot;
+
+/// The difference between two consecutive serialized snapshots.
+struct Delta {
+    /// Length of the older snapshot, which can differ when the resolution changed.
+    len: usize,
+    /// The XOR of both snapshots, run-length encoded as alternating zero run lengths and
+    /// literal runs, each prefixed with its length as a LEB128 number.
+    data: Vec<u8>,
+}
+
+fn write_length(data: &mut Vec<u8>, mut len: usize) {
+    loop {
+        let byte = (len & 0x7F) as u8;
+        len >>= 7;
+        if len == 0 {
+            data.push(byte);
+            return;
+        }
+        data.push(byte | 0x80);
+    }
+}
+
+fn read_length(data: &[u8], position: &mut usize) -> usize {
+    let mut len = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        len |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return len;
+        }
+    }
+}
+
+impl Delta {
+    /// Encodes how to get from `newer` back to `older`.
+    fn new(older: &[u8], newer: &[u8]) -> Self {
+        let xor = |i: usize| older.get(i).unwrap_or(&0) ^ newer.get(i).unwrap_or(&0);
+        let total = older.len().max(newer.len());
+        let mut data = Vec::new();
+        let mut i = 0;
+        while i < total {
+            let zeros_start = i;
+            while i < total && xor(i) == 0 {
+                i += 1;
+            }
+            write_length(&mut data, i - zeros_start);
+            let literal_start = i;
+            while i < total && xor(i) != 0 {
+                i += 1;
+            }
+            write_length(&mut data, i - literal_start);
+            data.extend((literal_start..i).map(xor));
+        }
+        Self {
+            len: older.len(),
+            data,
+        }
+    }
+
+    /// Turns `newer` back into the older snapshot.
+    fn apply(&self, newer: &mut Vec<u8>) {
+        newer.resize(newer.len().max(self.len), 0);
+        let mut position = 0;
+        let mut i = 0;
+        while position < self.data.len() {
+            i += read_length(&self.data, &mut position);
+            let literals = read_length(&self.data, &mut position);
+            for byte in &self.data[position..position + literals] {
+                newer[i] ^= byte;
+                i += 1;
+            }
+            position += literals;
+        }
+        newer.truncate(self.len);
+    }
+}
+
+/// A history of snapshots, one per frame, to step backwards through gameplay.
+///
+/// Only the latest snapshot is kept in full. Older ones are stored as the difference with the
+/// snapshot after them, which is usually tiny since little changes within a frame.
+pub struct RewindBuffer {
+    capacity: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer holding at most `capacity` snapshots.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// Maximum number of snapshots stored.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of snapshots stored.
+    pub fn len(&self) -> usize {
+        self.latest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    /// Adds a snapshot, dropping the oldest one if the buffer is full.
+    pub fn push(&mut self, snapshot: &Snapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        let bytes = snapshot.to_bytes();
+        if let Some(latest) = self.latest.replace(bytes) {
+            let newest = self.latest.as_deref().unwrap_or_default();
+            self.deltas.push_back(Delta::new(&latest, newest));
+        }
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Drops up to `frames` of the most recent snapshots and returns the one that is now the
+    /// latest, or `None` if the buffer is empty.
+    pub fn rewind(&mut self, frames: usize) -> Option<Snapshot> {
+        let latest = self.latest.as_mut()?;
+        for _ in 0..frames {
+            let Some(delta) = self.deltas.pop_back() else {
+                break;
+            };
+            delta.apply(latest);
+        }
+        Snapshot::from_bytes(latest).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8State, Quirks};
+
+    /// A snapshot after `frame` frames, switching to high resolution halfway so the size of
+    /// the framebuffer changes.
+    fn snapshot(frame: u8) -> Snapshot {
+        let mut state = Chip8State::new(Quirks::SCHIP_MODERN.memory_size);
+        state.data_registers[0] = frame;
+        state.ram[0x300 + frame as usize] = frame;
+        state.high_resolution = frame >= 5;
+        let pixels = if state.high_resolution {
+            128 * 64
+        } else {
+            64 * 32
+        };
+        let mut framebuffer = vec![0; pixels];
+        framebuffer[frame as usize] = 1;
+        Snapshot {
+            quirks: Quirks::SCHIP_MODERN,
+            state,
+            framebuffer,
+            key_states: frame as u16,
+            rng_state: frame as u64,
+            waiting_for_vblank: false,
+        }
+    }
+
+    #[test]
+    fn rewinding_returns_earlier_snapshots() {
+        let mut buffer = RewindBuffer::new(60);
+        for frame in 0..10 {
+            buffer.push(&snapshot(frame));
+        }
+        assert_eq!(buffer.len(), 10);
+        assert_eq!(buffer.rewind(0), Some(snapshot(9)));
+        assert_eq!(buffer.rewind(1), Some(snapshot(8)));
+        assert_eq!(buffer.rewind(4), Some(snapshot(4)));
+        assert_eq!(buffer.rewind(100), Some(snapshot(0)));
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn full_buffers_drop_the_oldest_snapshots() {
+        let mut buffer = RewindBuffer::new(3);
+        for frame in 0..10 {
+            buffer.push(&snapshot(frame));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.rewind(100), Some(snapshot(7)));
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.rewind(1), None);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    binary::{Reader, Writer},
+    Chip8Error, Chip8Result, Chip8State, Quirks,
+};
+
+/// Identifies a crab8 save state file.
+const MAGIC: &[u8; 8] = b"CRAB8SAV";
+
+/// A complete copy of a running machine, as taken by [`crate::Chip8Interpreter::snapshot`].
+///
+/// Snapshots are stored in a binary format starting with a magic number and a version, so
+/// snapshots written by older versions can still be read and newer ones are rejected cleanly.
+/// All numbers are big-endian.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub quirks: Quirks,
+    pub state: Chip8State,
+    /// Screen contents, as returned by [`crate::Framebuffer::pixels`].
+    pub framebuffer: Vec<u8>,
+    /// Keys held down, bit N for key N.
+    pub key_states: u16,
+    /// State of the random number generator, see [`crate::Chip8Random::state`].
+    pub rng_state: u64,
+    pub waiting_for_vblank: bool,
+}
+
+impl Snapshot {
+    /// Version of the format written by [`Self::to_bytes`].
+    pub const VERSION: u16 = 1;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes(MAGIC);
+        writer.u16(Self::VERSION);
+
+        writer.quirks(&self.quirks);
+
+        let state = &self.state;
+        writer.bytes(&state.data_registers);
+        writer.u16(state.index_register);
+        writer.u16(state.program_counter);
+        writer.u8(state.stack_pointer);
+        for address in state.stack {
+            writer.u16(address);
+        }
+        writer.u8(state.delay_timer);
+        writer.u8(state.sound_timer);
+        writer.u8(state.high_resolution as u8);
+        writer.bytes(&state.rpl_flags);
+        writer.u8(state.selected_planes);
+        writer.bytes(&state.audio_pattern);
+        writer.u8(state.pitch);
+        writer.u32(state.ram.len() as u32);
+        writer.bytes(&state.ram);
+
+        writer.u32(self.framebuffer.len() as u32);
+        writer.bytes(&self.framebuffer);
+        writer.u16(self.key_states);
+        writer.u64(self.rng_state);
+        writer.u8(self.waiting_for_vblank as u8);
+        writer.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Chip8Result<Self> {
+        let mut reader = Reader { bytes, invalid };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a crab8 save state"));
+        }
+        let version = reader.u16()?;
+        if version == 0 || version > Self::VERSION {
+            return Err(Chip8Error::UnsupportedSnapshotVersion { version });
+        }
+
+        let quirks = reader.quirks()?;
+
+        let mut state = Chip8State::new(0);
+        state.data_registers.copy_from_slice(reader.bytes(16)?);
+        state.index_register = reader.u16()?;
+        state.program_counter = reader.u16()?;
+        state.stack_pointer = reader.u8()?;
+        for address in state.stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        state.delay_timer = reader.u8()?;
+        state.sound_timer = reader.u8()?;
+        state.high_resolution = reader.u8()? != 0;
+        state.rpl_flags.copy_from_slice(reader.bytes(16)?);
+        state.selected_planes = reader.u8()?;
+        state.audio_pattern.copy_from_slice(reader.bytes(16)?);
+        state.pitch = reader.u8()?;
+        let ram_size = reader.u32()? as usize;
+        if ram_size != quirks.memory_size {
+            return Err(invalid("RAM size doesn't match the memory size"));
+        }
+        state.ram = reader.bytes(ram_size)?.to_vec();
+
+        let framebuffer_size = reader.u32()? as usize;
+        let expected_size = if state.high_resolution {
+            128 * 64
+        } else {
+            64 * 32
+        };
+        if framebuffer_size != expected_size {
+            return Err(invalid("framebuffer doesn't match the resolution"));
+        }
+        let framebuffer = reader.bytes(framebuffer_size)?.to_vec();
+        if framebuffer.iter().any(|pixel| *pixel > 3) {
+            return Err(invalid("pixel values must be at most 3"));
+        }
+        let key_states = reader.u16()?;
+        let rng_state = reader.u64()?;
+        let waiting_for_vblank = reader.u8()? != 0;
+        reader.finish()?;
+
+        Ok(Self {
+            quirks,
+            state,
+            framebuffer,
+            key_states,
+            rng_state,
+            waiting_for_vblank,
+        })
+    }
+}
+
+fn invalid(reason: &'static str) -> Chip8Error {
+    Chip8Error::InvalidSnapshot { reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut state = Chip8State::new(Quirks::XO_CHIP.memory_size);
+        state.data_registers[3] = 0x42;
+        state.index_register = 0x300;
+        state.ram[0x200..0x204].copy_from_slice(&[0x00, 0xE0, 0x12, 0x00]);
+        let mut framebuffer = vec![0; 64 * 32];
+        framebuffer[100] = 3;
+        Snapshot {
+            quirks: Quirks::XO_CHIP,
+            state,
+            framebuffer,
+            key_states: 0x8001,
+            rng_state: 1234,
+            waiting_for_vblank: true,
+        }
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let snapshot = snapshot();
+        assert_eq!(
+            Snapshot::from_bytes(&snapshot.to_bytes()).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn inconsistent_snapshots_are_rejected() {
+        let mut snapshot = snapshot();
+        snapshot.state.ram.truncate(0x1000);
+        assert!(Snapshot::from_bytes(&snapshot.to_bytes()).is_err());
+
+        let mut snapshot = self::snapshot();
+        snapshot.framebuffer[5] = 4;
+        assert!(Snapshot::from_bytes(&snapshot.to_bytes()).is_err());
+    }
+}
@@ -1,26 +1,38 @@
+use crate::{Chip8Error, Chip8Result};
+
+/// Address programs are loaded at.
+pub const PROGRAM_START: u16 = 0x200;
+
+/// Memory size of the original CHIP-8 and SUPER-CHIP.
+pub const CHIP8_MEMORY_SIZE: usize = 0x1000;
+/// Memory size of XO-CHIP, which can address a full 64 KiB.
+pub const XO_CHIP_MEMORY_SIZE: usize = 0x10000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Chip8State {
     pub data_registers: [u8; 16],
     pub index_register: u16,
     pub program_counter: u16,
     pub stack_pointer: u8,
-    pub ram: [u8; 4096],
+    pub ram: Vec<u8>,
     pub stack: [u16; 256],
     pub delay_timer: u8,
     pub sound_timer: u8,
+    /// Whether the SUPER-CHIP 128x64 mode is active.
+    pub high_resolution: bool,
+    /// RPL user flags, saved and restored with `FX75`/`FX85`. SUPER-CHIP has 8, XO-CHIP 16.
+    pub rpl_flags: [u8; 16],
+    /// XO-CHIP bitplanes that drawing, clearing and scrolling apply to.
+    pub selected_planes: u8,
+    /// XO-CHIP 1-bit audio pattern loaded with `F002`.
+    pub audio_pattern: [u8; 16],
+    /// XO-CHIP audio pitch register set with `FX3A`.
+    pub pitch: u8,
 }
 
 impl Default for Chip8State {
     fn default() -> Self {
-        Self {
-            data_registers: [0; 16],
-            index_register: 0,
-            program_counter: 0x200,
-            stack_pointer: 0,
-            ram: [0; 4096],
-            stack: [0; 256],
-            delay_timer: 0,
-            sound_timer: 0,
-        }
+        Self::new(CHIP8_MEMORY_SIZE)
     }
 }
 
@@ -43,17 +55,66 @@ const FONT: [u8; 16 * 5] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// Address of the 10 byte SUPER-CHIP font, right after the regular font.
+pub const BIG_FONT_ADDRESS: u16 = 0x50;
+
+const BIG_FONT: [u8; 16 * 10] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 impl Chip8State {
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            data_registers: [0; 16],
+            index_register: 0,
+            program_counter: PROGRAM_START,
+            stack_pointer: 0,
+            ram: vec![0; memory_size],
+            stack: [0; 256],
+            delay_timer: 0,
+            sound_timer: 0,
+            high_resolution: false,
+            rpl_flags: [0; 16],
+            selected_planes: 1,
+            audio_pattern: [0; 16],
+            pitch: 64,
+        }
+    }
+
     pub fn load_font_data(&mut self, fonts: &[u8]) {
         for (i, byte) in fonts.iter().enumerate() {
             self.ram[i] = *byte;
         }
     }
-    pub fn load_program(&mut self, program: &[u8]) {
+    pub fn load_program(&mut self, program: &[u8]) -> Chip8Result<()> {
+        let max_size = self.ram.len() - PROGRAM_START as usize;
+        if program.len() > max_size {
+            return Err(Chip8Error::ProgramTooLarge {
+                size: program.len(),
+                max_size,
+            });
+        }
         self.load_font_data(&FONT);
+        self.ram[BIG_FONT_ADDRESS as usize..][..BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         for (i, byte) in program.iter().enumerate() {
-            self.ram[0x200 + i] = *byte;
+            self.ram[PROGRAM_START as usize + i] = *byte;
         }
+        Ok(())
     }
 
     pub fn register(&self, register_index: u8) -> u8 {
@@ -0,0 +1,118 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    beeper::{DEFAULT_ATTACK, DEFAULT_FREQUENCY, DEFAULT_RELEASE},
+    Waveform,
+};
+
+/// The sound of the beeper, as samples between `-volume` and `volume`: the default tone, or the
+/// XO-CHIP audio pattern once a ROM sets one.
+///
+/// Switching the sound on or off ramps it up or down over a few milliseconds instead of cutting
+/// it off, which would click.
+pub struct Tone {
+    /// Frequency of the default tone in Hz.
+    pub frequency: f32,
+    pub waveform: Waveform,
+    pub volume: f32,
+    /// Seconds it takes to ramp up to full volume.
+    pub attack: f32,
+    /// Seconds it takes to ramp down to silence.
+    pub release: f32,
+    playing: bool,
+    /// Current level of the ramp, from 0 to 1.
+    level: f32,
+    /// The XO-CHIP audio pattern and its playback rate in samples per second.
+    pattern: Option<([u8; 16], f32)>,
+    /// Position in the default tone in periods, or in the pattern in bits.
+    phase: f32,
+    /// State of the 15-bit linear feedback shift register the noise is made with.
+    noise: u16,
+}
+
+impl Tone {
+    pub fn new(volume: f32) -> Self {
+        Self {
+            frequency: DEFAULT_FREQUENCY,
+            waveform: Waveform::default(),
+            volume,
+            attack: DEFAULT_ATTACK,
+            release: DEFAULT_RELEASE,
+            playing: false,
+            level: 0.,
+            pattern: None,
+            phase: 0.,
+            noise: 1,
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Switches to an XO-CHIP audio pattern, see [`crate::Chip8Beeper::set_pattern`].
+    pub fn set_pattern(&mut self, pattern: &[u8; 16], playback_rate: f32) {
+        if *pattern == [0; 16] {
+            self.pattern = None;
+            return;
+        }
+        if self.pattern.is_none() {
+            self.phase = 0.;
+        }
+        self.pattern = Some((*pattern, playback_rate));
+    }
+
+    /// The next sample at `sample_rate` samples per second.
+    pub fn next_sample(&mut self, sample_rate: u32) -> f32 {
+        let sample_rate = sample_rate as f32;
+        let value = match &self.pattern {
+            Some((pattern, playback_rate)) => {
+                let bit = self.phase as usize;
+                self.phase = (self.phase + playback_rate / sample_rate) % 128.;
+                if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                    1.
+                } else {
+                    -1.
+                }
+            }
+            None => {
+                let value = match self.waveform {
+                    Waveform::Square if self.phase < 0.5 => 1.,
+                    Waveform::Square => -1.,
+                    Waveform::Sine => (self.phase * TAU).sin(),
+                    Waveform::Triangle => 1. - 4. * (self.phase - 0.5).abs(),
+                    Waveform::Noise if self.noise & 1 != 0 => 1.,
+                    Waveform::Noise => -1.,
+                };
+                let phase = self.phase + self.frequency / sample_rate;
+                if (phase * 2.) as u32 != (self.phase * 2.) as u32 {
+                    let feedback = (self.noise ^ (self.noise >> 1)) & 1;
+                    self.noise = (self.noise >> 1) | (feedback << 14);
+                }
+                self.phase = phase % 1.;
+                value
+            }
+        };
+
+        let (target, ramp) = if self.playing {
+            (1., self.attack)
+        } else {
+            (0., self.release)
+        };
+        let step = if ramp > 0. {
+            1. / (ramp * sample_rate)
+        } else {
+            1.
+        };
+        self.level = if self.level < target {
+            (self.level + step).min(target)
+        } else {
+            (self.level - step).max(target)
+        };
+        value * self.level * self.volume
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{Chip8Beeper, Tone, Waveform};
+
+/// A beeper that captures everything another beeper is asked to play as 16-bit mono PCM, to
+/// save it as a WAV file.
+///
+/// The beeper is switched on or off once per frame by [`crate::Chip8Interpreter::tick_timers`],
+/// so every call to `play` or `pause` records exactly one 60 Hz frame of sound or silence.
+/// Nothing is recorded until [`Self::start`] is called.
+pub struct WavRecorder<B: Chip8Beeper> {
+    pub inner: B,
+    pub tone: Tone,
+    pub sample_rate: u32,
+    samples: Option<Vec<i16>>,
+    frames: u64,
+}
+
+impl<B: Chip8Beeper> WavRecorder<B> {
+    pub fn start(&mut self) {
+        self.samples = Some(Vec::new());
+        self.frames = 0;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.samples.is_some()
+    }
+
+    /// Stops recording and returns the WAV file, or `None` if nothing was being recorded.
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        let samples = self.samples.take()?;
+        let data_len = samples.len() as u32 * 2;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * 2).to_le_bytes());
+        // bytes per frame, bits per sample
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        Some(wav)
+    }
+
+    /// Records one frame of sound or silence.
+    fn record_frame(&mut self) {
+        let Some(samples) = &mut self.samples else {
+            return;
+        };
+        let rate = self.sample_rate as u64;
+        let len = ((self.frames + 1) * rate / 60 - self.frames * rate / 60) as usize;
+        self.frames += 1;
+        for _ in 0..len {
+            let sample = self.tone.next_sample(self.sample_rate);
+            samples.push((sample.clamp(-1., 1.) * i16::MAX as f32) as i16);
+        }
+    }
+}
+
+impl<B: Chip8Beeper> Chip8Beeper for WavRecorder<B> {
+    fn new(volume: f32) -> Self {
+        Self {
+            inner: B::new(volume),
+            tone: Tone::new(volume),
+            sample_rate: 44100,
+            samples: None,
+            frames: 0,
+        }
+    }
+
+    fn play(&mut self) {
+        self.inner.play();
+        self.tone.play();
+        self.record_frame();
+    }
+
+    fn pause(&mut self) {
+        self.inner.pause();
+        self.tone.pause();
+        self.record_frame();
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], playback_rate: f32) {
+        self.inner.set_pattern(pattern, playback_rate);
+        self.tone.set_pattern(pattern, playback_rate);
+    }
+
+    fn volume(&self) -> f32 {
+        self.tone.volume
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.inner.set_volume(volume);
+        self.tone.volume = volume;
+    }
+
+    fn set_frequency(&mut self, frequency: f32) {
+        self.inner.set_frequency(frequency);
+        self.tone.frequency = frequency;
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform) {
+        self.inner.set_waveform(waveform);
+        self.tone.waveform = waveform;
+    }
+
+    fn set_envelope(&mut self, attack: f32, release: f32) {
+        self.inner.set_envelope(attack, release);
+        self.tone.attack = attack;
+        self.tone.release = release;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NullBeeper;
+
+    fn u16_at(wav: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(wav[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(wav: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn frames_are_recorded_as_pcm() {
+        const FRAMES: usize = 3;
+        let mut recorder = WavRecorder::<NullBeeper>::new(0.5);
+        recorder.pause();
+        assert!(!recorder.is_recording());
+        recorder.start();
+        for _ in 0..FRAMES {
+            recorder.pause();
+        }
+        for _ in 0..FRAMES {
+            recorder.play();
+        }
+        assert!(recorder.inner.playing);
+        let wav = recorder.finish().unwrap();
+        assert!(recorder.finish().is_none());
+
+        let samples_per_frame = 44100 / 60;
+        let data_len = 2 * FRAMES as u32 * samples_per_frame * 2;
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4), 36 + data_len);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 16), 16);
+        assert_eq!(u16_at(&wav, 20), 1);
+        assert_eq!(u16_at(&wav, 22), 1);
+        assert_eq!(u32_at(&wav, 24), 44100);
+        assert_eq!(u32_at(&wav, 28), 44100 * 2);
+        assert_eq!(u16_at(&wav, 32), 2);
+        assert_eq!(u16_at(&wav, 34), 16);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), data_len);
+        assert_eq!(wav.len(), 44 + data_len as usize);
+
+        let samples: Vec<i16> = wav[44..]
+            .chunks(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        let (silent, sounding) = samples.split_at(samples.len() / 2);
+        assert!(silent.iter().all(|sample| *sample == 0));
+        assert!(sounding.iter().any(|sample| sample.abs() > i16::MAX / 4));
+    }
+}
@@ -0,0 +1,298 @@
+//! Runs test programs headlessly and compares the screen they end up with against golden images.
+//!
+//! The programs in `tests/programs` check the same things as the ROMs of Timendus' test suite,
+//! which aren't checked in. The tests for those ROMs are ignored by default, see
+//! `tests/roms/README.md` for how to run them and how to update the images.
+
+use std::{env, fs, path::PathBuf};
+
+use crab8_core::{
+    assemble, Chip8Beeper, Chip8Display, Chip8Keyboard, HeadlessDisplay, HeadlessInterpreter,
+    NullBeeper, Quirks, ScriptedKeyboard,
+};
+
+enum Source {
+    /// A ROM file in the test ROM directory.
+    Rom(&'static str),
+    /// An Octo program in `tests/programs`.
+    Program(&'static str),
+}
+
+struct Case {
+    /// Name of the golden image in `tests/golden`.
+    name: &'static str,
+    source: Source,
+    quirks: Quirks,
+    frames: u32,
+    /// Value stored at 0x1FF before running, which the test suite reads to skip its menus.
+    menu_choice: Option<u8>,
+    /// Keys to press and release, as `(frame, key, pressed)`.
+    keys: &'static [(u64, u8, bool)],
+}
+
+const DEFAULT: Case = Case {
+    name: "",
+    source: Source::Rom(""),
+    quirks: Quirks::COSMAC_VIP,
+    frames: 120,
+    menu_choice: None,
+    keys: &[],
+};
+
+fn manifest_path(path: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn load(case: &Case) -> Vec<u8> {
+    match case.source {
+        Source::Rom(file) => {
+            let directory = env::var_os("CRAB8_TEST_ROMS")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| manifest_path("tests/roms"));
+            let path = directory.join(file);
+            fs::read(&path).unwrap_or_else(|error| {
+                panic!(
+                    "could not read {}: {error}, see tests/roms/README.md",
+                    path.display()
+                )
+            })
+        }
+        Source::Program(file) => {
+            let source = fs::read_to_string(manifest_path("tests/programs").join(file)).unwrap();
+            assemble(&source).unwrap().program
+        }
+    }
+}
+
+fn check(case: Case) {
+    let program = load(&case);
+
+    let mut keyboard = ScriptedKeyboard::new();
+    for &(frame, key, pressed) in case.keys {
+        if pressed {
+            keyboard.press(frame, key);
+        } else {
+            keyboard.release(frame, key);
+        }
+    }
+    let mut interpreter = HeadlessInterpreter::new(
+        700,
+        case.quirks,
+        HeadlessDisplay::new(),
+        keyboard,
+        NullBeeper::new(0.),
+    );
+    interpreter.seed_rng(0);
+    interpreter.load_program(&program).unwrap();
+    if let Some(choice) = case.menu_choice {
+        interpreter.state.ram[0x1FF] = choice;
+    }
+    interpreter.run_frames(case.frames).unwrap();
+
+    let screen = interpreter.display.framebuffer.to_string();
+    let golden_path = manifest_path("tests/golden").join(format!("{}.txt", case.name));
+    if env::var_os("CRAB8_BLESS").is_some() {
+        fs::write(&golden_path, &screen).unwrap();
+        return;
+    }
+    let Ok(golden) = fs::read_to_string(&golden_path) else {
+        panic!(
+            "no golden image for {}, see tests/roms/README.md for how to bless it:\n{screen}",
+            case.name
+        );
+    };
+    assert!(
+        screen == golden,
+        "screen of {} doesn't match its golden image\nexpected:\n{golden}\ngot:\n{screen}",
+        case.name
+    );
+}
+
+#[test]
+fn arithmetic() {
+    check(Case {
+        name: "arithmetic",
+        source: Source::Program("arithmetic.8o"),
+        frames: 60,
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn logo() {
+    check(Case {
+        name: "logo",
+        source: Source::Program("logo.8o"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn opcodes() {
+    check(Case {
+        name: "opcodes",
+        source: Source::Program("opcodes.8o"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn flags() {
+    check(Case {
+        name: "flags",
+        source: Source::Program("flags.8o"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn quirks_vip() {
+    check(Case {
+        name: "quirks-vip",
+        source: Source::Program("quirks.8o"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn quirks_schip() {
+    check(Case {
+        name: "quirks-schip",
+        source: Source::Program("quirks.8o"),
+        quirks: Quirks::SCHIP_MODERN,
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn quirks_xochip() {
+    check(Case {
+        name: "quirks-xochip",
+        source: Source::Program("quirks.8o"),
+        quirks: Quirks::XO_CHIP,
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn keypad_key_down() {
+    check(Case {
+        name: "keypad-ex9e",
+        source: Source::Program("keypad.8o"),
+        keys: &[(30, 0x1, true), (30, 0xA, true)],
+        ..DEFAULT
+    });
+}
+
+#[test]
+fn keypad_get_key() {
+    check(Case {
+        name: "keypad-fx0a",
+        source: Source::Program("keypad.8o"),
+        frames: 180,
+        keys: &[(90, 0x7, true), (100, 0x7, false)],
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_chip8_logo() {
+    check(Case {
+        name: "suite-chip8-logo",
+        source: Source::Rom("1-chip8-logo.ch8"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_ibm_logo() {
+    check(Case {
+        name: "suite-ibm-logo",
+        source: Source::Rom("2-ibm-logo.ch8"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_corax_plus() {
+    check(Case {
+        name: "suite-corax+",
+        source: Source::Rom("3-corax+.ch8"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_flags() {
+    check(Case {
+        name: "suite-flags",
+        source: Source::Rom("4-flags.ch8"),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_quirks_chip8() {
+    check(Case {
+        name: "suite-quirks-chip8",
+        source: Source::Rom("5-quirks.ch8"),
+        frames: 600,
+        menu_choice: Some(1),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_quirks_schip() {
+    check(Case {
+        name: "suite-quirks-schip",
+        source: Source::Rom("5-quirks.ch8"),
+        quirks: Quirks::SCHIP_MODERN,
+        frames: 600,
+        menu_choice: Some(2),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_quirks_xochip() {
+    check(Case {
+        name: "suite-quirks-xochip",
+        source: Source::Rom("5-quirks.ch8"),
+        quirks: Quirks::XO_CHIP,
+        frames: 600,
+        menu_choice: Some(3),
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_keypad_key_down() {
+    check(Case {
+        name: "suite-keypad-ex9e",
+        source: Source::Rom("6-keypad.ch8"),
+        menu_choice: Some(1),
+        keys: &[(30, 0x1, true), (30, 0xA, true)],
+        ..DEFAULT
+    });
+}
+
+#[test]
+#[ignore = "needs the ROMs of Timendus' test suite, see tests/roms/README.md"]
+fn suite_keypad_get_key() {
+    check(Case {
+        name: "suite-keypad-fx0a",
+        source: Source::Rom("6-keypad.ch8"),
+        menu_choice: Some(3),
+        keys: &[(30, 0x7, true), (40, 0x7, false)],
+        ..DEFAULT
+    });
+}
@@ -0,0 +1,264 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use crab8_core::{Quirks, Waveform, MAX_CLOCK_SPEED, MIN_CLOCK_SPEED};
+use crossterm::style::Color;
+
+use crate::{keymap::Keymap, Palette, PLANE_COLORS};
+
+#[derive(Parser)]
+#[command(version, about = "A CHIP-8 emulator for the terminal")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub options: RunOptions,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Runs a ROM. Without a command, a ROM is picked from --rom-dir instead.
+    Run { rom: PathBuf },
+    /// Prints a ROM as Octo source.
+    Disasm { rom: PathBuf },
+    /// Assembles Octo source into a ROM, writing the symbols next to it in a `.sym` file.
+    Asm {
+        source: PathBuf,
+        /// Defaults to the source with a `.ch8` extension.
+        output: Option<PathBuf>,
+    },
+}
+
+/// The quirks of a CHIP-8 implementation.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum QuirksProfile {
+    /// The original COSMAC VIP interpreter.
+    Vip,
+    /// CHIP-48 on the HP-48 calculators.
+    Chip48,
+    /// SUPER-CHIP as emulated by modern interpreters.
+    Schip,
+    /// XO-CHIP.
+    Xochip,
+}
+
+impl QuirksProfile {
+    /// The profile for a ROM with the conventional extension for its platform.
+    pub fn for_rom(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("sc8") => QuirksProfile::Schip,
+            Some("xo8") => QuirksProfile::Xochip,
+            _ => QuirksProfile::Vip,
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirksProfile::Vip => Quirks::COSMAC_VIP,
+            QuirksProfile::Chip48 => Quirks::CHIP_48,
+            QuirksProfile::Schip => Quirks::SCHIP_MODERN,
+            QuirksProfile::Xochip => Quirks::XO_CHIP,
+        }
+    }
+}
+
+/// The random number generator used by `CXNN`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RngKind {
+    /// A uniform xorshift generator.
+    Xorshift,
+    /// A non-uniform generator that reads the program's first page, like the COSMAC VIP
+    /// interpreter read its own code. It doesn't reproduce the VIP's sequence.
+    ProgramPage,
+}
+
+#[derive(Args)]
+pub struct RunOptions {
+    /// Instructions executed per second. Defaults to the ROM database's tick rate, or 700.
+    #[arg(
+        long,
+        global = true,
+        value_name = "HZ",
+        value_parser = clap::value_parser!(u32).range(MIN_CLOCK_SPEED as i64..=MAX_CLOCK_SPEED as i64)
+    )]
+    pub clock: Option<u32>,
+    /// Volume of the beeper, from 0 to 1.
+    #[arg(long, global = true, default_value_t = 0.1, value_parser = parse_volume)]
+    pub volume: f32,
+    /// Frequency of the beeper in Hz.
+    #[arg(long, global = true, default_value_t = 440., value_name = "HZ", value_parser = parse_frequency)]
+    pub frequency: f32,
+    /// Shape of the beeper's tone.
+    #[arg(long, global = true, default_value = "sine", value_parser = parse_waveform)]
+    pub waveform: Waveform,
+    /// Milliseconds the beeper takes to ramp up when it starts, from 0 to 1000.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 2,
+        value_name = "MS",
+        value_parser = clap::value_parser!(u32).range(0..=1000)
+    )]
+    pub attack: u32,
+    /// Milliseconds the beeper takes to ramp down when it stops, from 0 to 1000.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 10,
+        value_name = "MS",
+        value_parser = clap::value_parser!(u32).range(0..=1000)
+    )]
+    pub release: u32,
+    /// Quirks to run with. Defaults to the platform in the ROM database, or a profile based on
+    /// the ROM's extension.
+    #[arg(long, global = true, value_name = "PROFILE")]
+    pub quirks: Option<QuirksProfile>,
+    /// Comma separated colours for pixels that are off and on, followed by the colours of the
+    /// second and both XO-CHIP planes. Colours are names like `dark_yellow` or `#RRGGBB`.
+    /// Defaults to the colours in the ROM database, or `reset,yellow,dark_red,dark_yellow`.
+    #[arg(long, global = true, value_parser = parse_palette)]
+    pub colors: Option<Palette>,
+    /// A keymap preset (qwerty, azerty, qwertz or dvorak), a keymap file ending in `.toml`, or
+    /// the 16 keys to use for hex keys 0 up to F.
+    #[arg(
+        long,
+        global = true,
+        value_name = "KEYMAP",
+        value_parser = parse_keymap,
+        default_value = "qwerty"
+    )]
+    pub keymap: Keymap,
+    /// How long a key stays down after it is pressed, in milliseconds, in terminals that don't
+    /// report key releases. Keys the terminal repeats stay down until it stops repeating them, so
+    /// this should be longer than the key repeat delay. From 50 to 5000.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 500,
+        value_name = "MS",
+        value_parser = clap::value_parser!(u64).range(50..=5000)
+    )]
+    pub key_hold: u64,
+    /// Directory to pick a ROM from when no ROM is given.
+    #[arg(long, global = true, default_value = "./testroms", value_name = "DIR")]
+    pub rom_dir: PathBuf,
+    /// A ROM database in the `programs.json` format of the community CHIP-8 database, to use
+    /// instead of the bundled one.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub rom_db: Option<PathBuf>,
+    /// Seed of the random number generator, to make runs reproducible.
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+    /// Random number generator for `CXNN`. Movies are played back with the one they were
+    /// recorded with.
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value = "xorshift",
+        conflicts_with = "play"
+    )]
+    pub rng: RngKind,
+    /// Starts the ROM paused in the debugger.
+    #[arg(long, global = true, conflicts_with_all = ["record", "play", "headless"])]
+    pub debug: bool,
+    /// Records the keypad input into a movie file.
+    #[arg(long, global = true, value_name = "FILE", conflicts_with_all = ["play", "headless"])]
+    pub record: Option<PathBuf>,
+    /// Replays a movie file recorded with --record.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub play: Option<PathBuf>,
+    /// Runs without a terminal display, keyboard or audio, as fast as possible, and then prints
+    /// the screen. Needs --frames or --play.
+    #[arg(long, global = true)]
+    pub headless: bool,
+    /// Number of frames to run in headless mode.
+    #[arg(long, global = true, value_name = "N", requires = "headless")]
+    pub frames: Option<u64>,
+    /// Saves the final screen as a PNG in headless mode instead of printing it.
+    #[arg(long, global = true, value_name = "FILE", requires = "headless")]
+    pub screenshot: Option<PathBuf>,
+    /// Saves all sound to a WAV file, also in headless mode.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub wav: Option<PathBuf>,
+    /// Size of a CHIP-8 pixel in screenshots and GIFs, in image pixels.
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 8,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..=64)
+    )]
+    pub scale: u32,
+}
+
+fn parse_volume(text: &str) -> Result<f32, String> {
+    let volume: f32 = text
+        .parse()
+        .map_err(|_| format!("'{text}' isn't a number"))?;
+    if !(0. ..=1.).contains(&volume) {
+        return Err("volume must be between 0 and 1".to_string());
+    }
+    Ok(volume)
+}
+
+fn parse_frequency(text: &str) -> Result<f32, String> {
+    let frequency: f32 = text
+        .parse()
+        .map_err(|_| format!("'{text}' isn't a number"))?;
+    if !(20. ..=20_000.).contains(&frequency) {
+        return Err("frequency must be between 20 and 20000 Hz".to_string());
+    }
+    Ok(frequency)
+}
+
+fn parse_waveform(text: &str) -> Result<Waveform, String> {
+    match text {
+        "square" => Ok(Waveform::Square),
+        "sine" => Ok(Waveform::Sine),
+        "triangle" => Ok(Waveform::Triangle),
+        "noise" => Ok(Waveform::Noise),
+        _ => Err(format!(
+            "'{text}' isn't a waveform, use square, sine, triangle or noise"
+        )),
+    }
+}
+
+fn parse_color(text: &str) -> Result<Color, String> {
+    if let Some(hex) = text.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)
+            .ok_or_else(|| format!("'{text}' isn't a colour like #RRGGBB"))?;
+        let [_, r, g, b] = value.to_be_bytes();
+        return Ok(Color::Rgb { r, g, b });
+    }
+    Color::try_from(text).map_err(|_| format!("'{text}' isn't a colour name or #RRGGBB"))
+}
+
+pub fn parse_palette(text: &str) -> Result<Palette, String> {
+    let colors = text
+        .split(',')
+        .map(|color| parse_color(color.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !(2..=4).contains(&colors.len()) {
+        return Err("expected 2 to 4 colours".to_string());
+    }
+    let mut palette = PLANE_COLORS;
+    palette[..colors.len()].copy_from_slice(&colors);
+    Ok(palette)
+}
+
+fn parse_keymap(text: &str) -> Result<Keymap, String> {
+    if let Some(keymap) = Keymap::preset(text) {
+        return Ok(keymap);
+    }
+    let path = Path::new(text);
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "toml")
+    {
+        return Keymap::from_file(path);
+    }
+    Keymap::from_layout(text)
+}
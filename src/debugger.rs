@@ -0,0 +1,348 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crab8_core::{
+    Chip8Clock, Chip8Display, Chip8Error, Chip8Keyboard, Chip8Random, Chip8Result, Instruction,
+};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    queue,
+    style::{self, Stylize},
+    terminal,
+};
+
+use crate::{handle_hotkey, key_code, save_slots::SaveSlots, Interpreter};
+
+/// Width of the debug panes, in columns.
+const PANE_WIDTH: usize = 44;
+/// Number of instructions shown in the disassembly pane.
+const DISASSEMBLY_LINES: usize = 9;
+/// Number of rows of 8 bytes shown in the RAM pane.
+const MEMORY_ROWS: usize = 8;
+
+/// Pauses execution when a watched address is read or written.
+struct Watchpoint {
+    address: u16,
+    read: bool,
+    write: bool,
+}
+
+struct Debugger {
+    paused: bool,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    /// Start of the RAM view, or `None` to follow I.
+    memory_view: Option<u16>,
+    message: String,
+}
+
+/// Runs the loaded ROM at `path` with the debug panes shown next to the display. Execution
+/// starts paused.
+///
+/// While paused, F10 steps a single instruction, F5 continues, F9 toggles a breakpoint at PC and
+/// `:` opens a command prompt. F6 pauses a running program. Esc quits either way. The other
+/// hotkeys work both while paused and while running, except that F7 rewinds a single frame
+/// while paused.
+pub fn run<R: Chip8Random>(interpreter: &mut Interpreter<R>, path: &Path) -> Chip8Result<()> {
+    let mut save_slots = SaveSlots::new(path);
+    let mut debugger = Debugger {
+        paused: true,
+        breakpoints: BTreeSet::new(),
+        watchpoints: Vec::new(),
+        memory_view: None,
+        message: String::new(),
+    };
+    let frame_time = Duration::from_secs_f64(1. / 60.);
+    let mut next_frame = interpreter.clock.now();
+    loop {
+        if debugger.paused {
+            debugger.render(interpreter)?;
+            match read_key()? {
+                KeyCode::F(5) => {
+                    debugger.paused = false;
+                    debugger.message.clear();
+                    // Don't stop at the breakpoint we're currently paused on.
+                    debugger.step(interpreter)?;
+                    next_frame = interpreter.clock.now();
+                }
+                KeyCode::F(10) => {
+                    debugger.message.clear();
+                    debugger.step(interpreter)?;
+                }
+                KeyCode::F(9) => debugger.toggle_breakpoint(interpreter.state.program_counter),
+                KeyCode::F(7) => {
+                    debugger.message = if interpreter.rewind(1)? {
+                        "Rewound one frame".to_string()
+                    } else {
+                        "Nothing to rewind".to_string()
+                    };
+                }
+                KeyCode::Char(':') => {
+                    let command = debugger.prompt(interpreter)?;
+                    if !debugger.command(&command) {
+                        return Ok(());
+                    }
+                }
+                KeyCode::Esc => return Ok(()),
+                key => {
+                    if let Some(message) = handle_hotkey(key, interpreter, &mut save_slots) {
+                        debugger.message = message;
+                    }
+                }
+            }
+        } else {
+            // Same frame order as `Chip8Interpreter::run_paced_frame`: input, cycles, timers.
+            let time_left = interpreter.clock.time_until(next_frame);
+            next_frame += frame_time;
+            interpreter
+                .keyboard
+                .update_keystates(time_left.as_micros() as u64)?;
+            debugger.run_frame(interpreter)?;
+            for key in interpreter.keyboard.inner.take_hotkeys() {
+                if key == KeyCode::Esc {
+                    return Ok(());
+                } else if key == KeyCode::F(6) {
+                    debugger.paused = true;
+                    debugger.message = "Paused".to_string();
+                } else if let Some(message) = handle_hotkey(key, interpreter, &mut save_slots) {
+                    debugger.message = message;
+                }
+            }
+        }
+    }
+}
+
+fn read_key() -> io::Result<KeyCode> {
+    loop {
+        if let Event::Key(
+            event @ KeyEvent {
+                kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                ..
+            },
+        ) = event::read()?
+        {
+            return Ok(key_code(&event));
+        }
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    let digits = text.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).ok()
+}
+
+impl Debugger {
+    /// Executes one frame worth of instructions, pausing on breakpoints and watchpoints.
+    fn run_frame<R: Chip8Random>(&mut self, interpreter: &mut Interpreter<R>) -> Chip8Result<()> {
+        for _ in 0..interpreter.cycles_per_frame() {
+            let pc = interpreter.state.program_counter;
+            if self.breakpoints.contains(&pc) {
+                self.paused = true;
+                self.message = format!("Breakpoint at {pc:03X}");
+                return Ok(());
+            }
+            let waiting_for_vblank = self.step(interpreter)?;
+            if self.paused || waiting_for_vblank {
+                break;
+            }
+        }
+        interpreter.tick_timers()?;
+        Ok(())
+    }
+
+    /// Executes a single instruction, pausing when it faults, exits or hits a watchpoint.
+    /// Returns whether the interpreter is waiting for the next frame.
+    fn step<R: Chip8Random>(&mut self, interpreter: &mut Interpreter<R>) -> Chip8Result<bool> {
+        let pc = interpreter.state.program_counter;
+        let result = match interpreter.step() {
+            Ok(result) => result,
+            Err(Chip8Error::Io(error)) => return Err(error.into()),
+            Err(error) => {
+                interpreter.state.program_counter = pc;
+                self.paused = true;
+                self.message = error.to_string();
+                return Ok(false);
+            }
+        };
+        if self.paused {
+            if result.waiting_for_vblank {
+                interpreter.tick_timers()?;
+                return self.step(interpreter);
+            }
+            interpreter.display.present(&interpreter.framebuffer)?;
+        }
+        if result.exited {
+            self.paused = true;
+            self.message = "The program exited".to_string();
+        }
+        for watchpoint in &self.watchpoints {
+            let read = watchpoint.read
+                && result
+                    .memory_read
+                    .is_some_and(|access| access.contains(watchpoint.address));
+            let written = watchpoint.write
+                && result
+                    .memory_written
+                    .is_some_and(|access| access.contains(watchpoint.address));
+            if read || written {
+                let verb = if written { "written" } else { "read" };
+                self.paused = true;
+                self.message = format!("{:03X} {verb} at {pc:03X}", watchpoint.address);
+            }
+        }
+        Ok(result.waiting_for_vblank)
+    }
+
+    fn toggle_breakpoint(&mut self, address: u16) {
+        if self.breakpoints.remove(&address) {
+            self.message = format!("Removed breakpoint at {address:03X}");
+        } else {
+            self.breakpoints.insert(address);
+            self.message = format!("Added breakpoint at {address:03X}");
+        }
+    }
+
+    /// Executes a prompt command. Returns `false` if the debugger should quit.
+    fn command(&mut self, command: &str) -> bool {
+        let words: Vec<_> = command.split_whitespace().collect();
+        let address = words.get(1).and_then(|word| parse_address(word));
+        match (words.first().copied(), address) {
+            (None, _) => {}
+            (Some("q"), _) => return false,
+            (Some("b"), Some(address)) => self.toggle_breakpoint(address),
+            (Some(kind @ ("r" | "w" | "rw")), Some(address)) => {
+                self.watchpoints
+                    .retain(|watchpoint| watchpoint.address != address);
+                self.watchpoints.push(Watchpoint {
+                    address,
+                    read: kind.contains('r'),
+                    write: kind.contains('w'),
+                });
+                self.message = format!("Watching {address:03X}");
+            }
+            (Some("d"), Some(address)) => {
+                self.watchpoints
+                    .retain(|watchpoint| watchpoint.address != address);
+                self.message = format!("Removed watchpoint at {address:03X}");
+            }
+            (Some("m"), address) => self.memory_view = address,
+            _ => {
+                self.message = "Commands: b/r/w/rw/d ADDR, m [ADDR], q".to_string();
+            }
+        }
+        true
+    }
+
+    /// Reads a command on the status line.
+    fn prompt<R: Chip8Random>(&mut self, interpreter: &mut Interpreter<R>) -> io::Result<String> {
+        let mut command = String::new();
+        loop {
+            self.message = format!(":{command}_");
+            self.render(interpreter)?;
+            match read_key()? {
+                KeyCode::Enter => break,
+                KeyCode::Esc => {
+                    command.clear();
+                    break;
+                }
+                KeyCode::Backspace => {
+                    command.pop();
+                }
+                KeyCode::Char(c) => command.push(c),
+                _ => {}
+            }
+        }
+        self.message.clear();
+        Ok(command)
+    }
+
+    fn render<R: Chip8Random>(&self, interpreter: &mut Interpreter<R>) -> io::Result<()> {
+        let state = &interpreter.state;
+        let mut lines = Vec::new();
+
+        let status = if self.paused { "PAUSED" } else { "RUNNING" };
+        lines.push(status.to_string());
+        lines.push(String::new());
+        for (row, registers) in state.data_registers.chunks(4).enumerate() {
+            let registers: Vec<_> = registers
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X} {value:02X}", row * 4 + i))
+                .collect();
+            lines.push(registers.join("  "));
+        }
+        lines.push(format!(
+            "PC {:03X}  I {:03X}  DT {:02X}  ST {:02X}",
+            state.program_counter, state.index_register, state.delay_timer, state.sound_timer
+        ));
+        let stack: Vec<_> = (1..=state.stack_pointer as usize)
+            .rev()
+            .take(6)
+            .map(|i| format!("{:03X}", state.stack[i]))
+            .collect();
+        lines.push(format!(
+            "SP {:X}  [{}]",
+            state.stack_pointer,
+            stack.join(" ")
+        ));
+        lines.push(String::new());
+
+        let mut address = state.program_counter.saturating_sub(6);
+        for _ in 0..DISASSEMBLY_LINES {
+            let marker = match (
+                address == state.program_counter,
+                self.breakpoints.contains(&address),
+            ) {
+                (true, _) => ">",
+                (false, true) => "*",
+                _ => " ",
+            };
+            let instruction = state
+                .ram
+                .get(address as usize..)
+                .and_then(|bytes| Instruction::decode_bytes(bytes).ok());
+            let text = match instruction {
+                Some(instruction) => instruction.to_string(),
+                None => "???".to_string(),
+            };
+            lines.push(format!("{marker}{address:03X}  {text}"));
+            address = address.wrapping_add(instruction.map_or(2, |i| i.size()));
+        }
+        lines.push(String::new());
+
+        let start = self.memory_view.unwrap_or(state.index_register) & !0x7;
+        for row in 0..MEMORY_ROWS {
+            let address = start as usize + row * 8;
+            let Some(bytes) = state.ram.get(address..(address + 8).min(state.ram.len())) else {
+                break;
+            };
+            let hex: Vec<_> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+            lines.push(format!("{address:03X}: {}", hex.join(" ")));
+        }
+        lines.push(String::new());
+        lines.push(self.message.clone());
+
+        let column = interpreter.framebuffer.width() as u16 + 2;
+        let (_, rows) = terminal::size()?;
+        let stdout = &mut interpreter.display.stdout;
+        for (row, line) in lines.iter().enumerate().take(rows as usize) {
+            let line = format!("{line:<PANE_WIDTH$}");
+            let content = if row == 0 {
+                line.bold()
+            } else {
+                line.stylize()
+            };
+            queue!(
+                stdout,
+                cursor::MoveTo(column, row as u16),
+                style::PrintStyledContent(content)
+            )?;
+        }
+        stdout.flush()
+    }
+}
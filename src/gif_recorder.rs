@@ -0,0 +1,142 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crab8_core::{Framebuffer, MAX_HEIGHT, MAX_WIDTH};
+
+use crate::{screenshot::rgb, Palette};
+
+/// Shortest frame delay written, in hundredths of a second. Most viewers show frames with a
+/// shorter delay for a tenth of a second instead.
+const MIN_DELAY: u64 = 2;
+
+/// Records presented frames into an animated GIF.
+///
+/// Frames are presented at 60 Hz, but GIF delays are in hundredths of a second. Every frame
+/// starts at the hundredth closest to its real start time, and frames that would be shown for
+/// less than [`MIN_DELAY`] are dropped, so the animation keeps the right speed. Identical
+/// consecutive frames are stored once.
+///
+/// The GIF always has room for the SUPER-CHIP resolution, so low resolution pixels are drawn
+/// twice as big.
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    /// Size of a high resolution pixel in image pixels.
+    scale: usize,
+    /// Number of frames presented so far.
+    frames: u64,
+    /// The frame waiting to be written, with the frame number it was first presented at.
+    pending: Option<(Framebuffer, u64)>,
+}
+
+/// Converts a frame number to hundredths of a second.
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + 30) / 60
+}
+
+/// Whether both frames look the same, ignoring state that isn't shown like the selected planes.
+fn same_image(a: &Framebuffer, b: &Framebuffer) -> bool {
+    a.high_resolution() == b.high_resolution() && a.pixels() == b.pixels()
+}
+
+impl GifRecorder {
+    /// Starts recording to the file at `path`. Low resolution pixels are `scale` image pixels
+    /// big, rounded down to an even number so high resolution pixels are exactly half that.
+    pub fn new(path: &Path, palette: &Palette, scale: u32) -> io::Result<Self> {
+        let scale = (scale as usize / 2).max(1);
+        let colors: Vec<u8> = palette.iter().flat_map(|color| rgb(*color)).collect();
+        let mut encoder = gif::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            (MAX_WIDTH * scale) as u16,
+            (MAX_HEIGHT * scale) as u16,
+            &colors,
+        )
+        .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            encoder,
+            scale,
+            frames: 0,
+            pending: None,
+        })
+    }
+
+    /// Adds the next 60 Hz frame.
+    pub fn push(&mut self, framebuffer: &Framebuffer) -> io::Result<()> {
+        let frame = self.frames;
+        self.frames += 1;
+        match &mut self.pending {
+            Some((pending, _)) if same_image(pending, framebuffer) => Ok(()),
+            Some((pending, start)) if centiseconds(frame) - centiseconds(*start) < MIN_DELAY => {
+                pending.clone_from(framebuffer);
+                Ok(())
+            }
+            _ => {
+                let previous = self.pending.replace((framebuffer.clone(), frame));
+                match previous {
+                    Some((pending, start)) => self.write(&pending, start, frame),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Writes the last frame and finishes the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some((pending, start)) = self.pending.take() {
+            let end = self.frames.max(start + 1);
+            self.write(&pending, start, end)?;
+        }
+        self.encoder.into_inner()?.flush()
+    }
+
+    /// Writes `framebuffer`, shown from frame `start` until `end`.
+    fn write(&mut self, framebuffer: &Framebuffer, start: u64, end: u64) -> io::Result<()> {
+        let pixel_size = self.scale * MAX_WIDTH / framebuffer.width();
+        let width = MAX_WIDTH * self.scale;
+        let mut pixels = Vec::with_capacity(width * MAX_HEIGHT * self.scale);
+        for row in framebuffer.pixels().chunks(framebuffer.width()) {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| [pixel & 0b11].repeat(pixel_size))
+                .collect();
+            for _ in 0..pixel_size {
+                pixels.extend_from_slice(&line);
+            }
+        }
+        let delay = (centiseconds(end) - centiseconds(start)).max(MIN_DELAY);
+        let frame = gif::Frame {
+            width: width as u16,
+            height: (MAX_HEIGHT * self.scale) as u16,
+            delay: delay.min(u16::MAX as u64) as u16,
+            buffer: Cow::Owned(pixels),
+            ..Default::default()
+        };
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selecting_planes_does_not_change_the_image() {
+        let frame = Framebuffer::new();
+        let mut selected = frame.clone();
+        selected.select_planes(3);
+        assert!(same_image(&frame, &selected));
+
+        let mut drawn = selected.clone();
+        drawn.draw(0, 0, &[0x80], false);
+        assert!(!same_image(&selected, &drawn));
+        let mut high_resolution = frame.clone();
+        high_resolution.set_high_resolution(true);
+        assert!(!same_image(&frame, &high_resolution));
+    }
+}
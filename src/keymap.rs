@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crossterm::event::KeyCode;
+use serde::Deserialize;
+
+/// Built-in layouts, with the keypad on the block of keys that is 1234/QWER/ASDF/ZXCV on a
+/// QWERTY keyboard. Every string lists the keys for hex keys 0 up to F, and AZERTY also binds
+/// the unshifted characters of its number row.
+const PRESETS: [(&str, &[&str]); 4] = [
+    ("qwerty", &["x123qweasdzc4rfv"]),
+    ("azerty", &["x123azeqsdwc4rfv", "x&é\"azeqsdwc'rfv"]),
+    ("qwertz", &["x123qweasdyc4rfv"]),
+    ("dvorak", &["q123',.aoe;j4puk"]),
+];
+
+/// Maps terminal keys to keys of the hex keypad. Any number of terminal keys can be mapped to
+/// the same hex key.
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    keys: HashMap<KeyCode, u8>,
+    /// Bindings for specific ROMs by file name, replacing those of the hex keys they list.
+    rom_bindings: HashMap<String, Bindings>,
+}
+
+/// Terminal keys by hex key.
+type Bindings = BTreeMap<u8, Vec<KeyCode>>;
+
+/// A keymap file, see the README for an example.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeymapFile {
+    /// The preset to start from, QWERTY if there is none.
+    preset: Option<String>,
+    /// Bindings replacing those of the preset, by hex key.
+    #[serde(default)]
+    keys: BTreeMap<String, KeyNames>,
+    /// Bindings for specific ROMs by file name.
+    #[serde(default)]
+    roms: HashMap<String, BTreeMap<String, KeyNames>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeyNames {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("qwerty").unwrap()
+    }
+}
+
+impl Keymap {
+    /// The built-in preset called `name`, like `azerty`.
+    pub fn preset(name: &str) -> Option<Self> {
+        let (_, layouts) = PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))?;
+        let mut keymap = Self::from_layout(layouts[0]).unwrap();
+        for layout in &layouts[1..] {
+            keymap.keys.extend(Self::from_layout(layout).unwrap().keys);
+        }
+        Some(keymap)
+    }
+
+    /// Names of the built-in presets.
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _)| *name)
+    }
+
+    /// Parses a layout of 16 characters, the keys for hex keys 0 up to F.
+    pub fn from_layout(layout: &str) -> Result<Self, String> {
+        let chars: Vec<char> = layout.chars().collect();
+        if chars.len() != 16 {
+            return Err(format!(
+                "expected 16 keys, one for every hex key from 0 to F, but got {}",
+                chars.len()
+            ));
+        }
+        let mut keys = HashMap::new();
+        for (hex_key, char) in chars.into_iter().enumerate() {
+            if keys
+                .insert(KeyCode::Char(char.to_ascii_lowercase()), hex_key as u8)
+                .is_some()
+            {
+                return Err(format!("'{char}' is used for more than one key"));
+            }
+        }
+        Ok(Self {
+            keys,
+            rom_bindings: HashMap::new(),
+        })
+    }
+
+    /// Reads a keymap file in TOML.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("could not read {}: {error}", path.display()))?;
+        let file: KeymapFile = toml::from_str(&text).map_err(|error| error.to_string())?;
+        let preset = file.preset.as_deref().unwrap_or("qwerty");
+        let mut keymap = Self::preset(preset).ok_or_else(|| {
+            let names: Vec<_> = Self::preset_names().collect();
+            format!(
+                "there is no preset called '{preset}', use one of {}",
+                names.join(", ")
+            )
+        })?;
+        keymap.bind(&parse_bindings(&file.keys)?);
+        for (rom, bindings) in &file.roms {
+            let bindings = parse_bindings(bindings).map_err(|error| format!("{rom}: {error}"))?;
+            keymap.rom_bindings.insert(rom.clone(), bindings);
+        }
+        Ok(keymap)
+    }
+
+    /// The keymap to use for the ROM at `path`, with its bindings from the keymap file.
+    pub fn for_rom(&self, path: &Path) -> Self {
+        let mut keymap = self.clone();
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if let Some(bindings) = file_name.and_then(|name| self.rom_bindings.get(name)) {
+            keymap.bind(bindings);
+        }
+        keymap
+    }
+
+    /// Replaces the terminal keys of the hex keys in `bindings`. Terminal keys that were mapped
+    /// to another hex key are moved.
+    fn bind(&mut self, bindings: &Bindings) {
+        self.keys
+            .retain(|_, hex_key| !bindings.contains_key(hex_key));
+        for (hex_key, codes) in bindings {
+            for code in codes {
+                self.keys.insert(*code, *hex_key);
+            }
+        }
+    }
+
+    /// The hex key `code` is mapped to.
+    pub fn hex_key(&self, code: KeyCode) -> Option<u8> {
+        let code = match code {
+            KeyCode::Char(char) => KeyCode::Char(char.to_ascii_lowercase()),
+            code => code,
+        };
+        self.keys.get(&code).copied()
+    }
+}
+
+/// Parses bindings from a keymap file, checking that no terminal key is used twice.
+fn parse_bindings(bindings: &BTreeMap<String, KeyNames>) -> Result<Bindings, String> {
+    let mut used = HashSet::new();
+    let mut parsed = Bindings::new();
+    for (hex_key, names) in bindings {
+        let hex_key = u8::from_str_radix(hex_key, 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(|| format!("'{hex_key}' isn't a hex key from 0 to F"))?;
+        let names = match names {
+            KeyNames::One(name) => std::slice::from_ref(name),
+            KeyNames::Many(names) => names.as_slice(),
+        };
+        let mut codes = Vec::new();
+        for name in names {
+            let code = parse_key(name)?;
+            if !used.insert(code) {
+                return Err(format!("'{name}' is used for more than one key"));
+            }
+            codes.push(code);
+        }
+        parsed.insert(hex_key, codes);
+    }
+    Ok(parsed)
+}
+
+/// Parses a terminal key: a single character, or a name like `up` or `space`.
+fn parse_key(name: &str) -> Result<KeyCode, String> {
+    let mut chars = name.chars();
+    if let (Some(char), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(char.to_ascii_lowercase()));
+    }
+    match name.to_ascii_lowercase().as_str() {
+        "up" => Ok(KeyCode::Up),
+        "down" => Ok(KeyCode::Down),
+        "left" => Ok(KeyCode::Left),
+        "right" => Ok(KeyCode::Right),
+        "space" => Ok(KeyCode::Char(' ')),
+        "enter" => Ok(KeyCode::Enter),
+        "tab" => Ok(KeyCode::Tab),
+        "backspace" => Ok(KeyCode::Backspace),
+        "insert" => Ok(KeyCode::Insert),
+        "delete" => Ok(KeyCode::Delete),
+        "home" => Ok(KeyCode::Home),
+        "end" => Ok(KeyCode::End),
+        "pageup" => Ok(KeyCode::PageUp),
+        "pagedown" => Ok(KeyCode::PageDown),
+        _ => Err(format!("'{name}' isn't a key")),
+    }
+}
@@ -1,23 +1,77 @@
+use clap::{CommandFactory, Parser};
+use cli::{Cli, Command, QuirksProfile, RngKind, RunOptions};
 use cpal::{BuildStreamError, Device, FromSample, SizedSample, Stream, StreamConfig};
-use crab8_core::{Chip8Beeper, Chip8Display, Chip8Interpreter, Chip8Keyboard};
+use crab8_core::{
+    assemble, Chip8Beeper, Chip8Clock, Chip8Display, Chip8Error, Chip8Interpreter, Chip8Keyboard,
+    Chip8Random, Chip8Result, Disassembly, Framebuffer, HeadlessDisplay, Movie, MovieKeyboard,
+    NullBeeper, ProgramPageRng, Quirks, RandomKind, ScriptedKeyboard, Tone, VirtualClock,
+    WavRecorder, Waveform, XorShiftRng,
+};
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute, queue,
     style::{self, Stylize},
     terminal,
 };
+use gif_recorder::GifRecorder;
+use keymap::Keymap;
+use rom_database::RomDatabase;
+use save_slots::SaveSlots;
 use std::{
-    f32::consts::TAU,
     fs,
     io::{self, stdout, ErrorKind, Stdout, Write},
     path::{Path, PathBuf},
-    time::{Duration, Instant},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
+mod cli;
+mod debugger;
+mod gif_recorder;
+mod keymap;
+mod rom_database;
+mod save_slots;
+mod screenshot;
+
+type Interpreter<R> = Chip8Interpreter<
+    CrossTermDisplay,
+    MovieKeyboard<CrossTermKeyboard>,
+    WavRecorder<CpalBeeper>,
+    R,
+>;
+
+/// How long a status message stays below the screen.
+const STATUS_TIME: Duration = Duration::from_secs(3);
+
+/// Terminal colours for a pixel, indexed by the bitplanes it is set in.
+pub type Palette = [style::Color; 4];
+
+/// The default palette.
+const PLANE_COLORS: Palette = [
+    style::Color::Reset,
+    style::Color::Yellow,
+    style::Color::DarkRed,
+    style::Color::DarkYellow,
+];
+
 pub struct CrossTermDisplay {
     stdout: Stdout,
-    display: [bool; 64 * 32],
+    pub colors: Palette,
+    /// Size of a CHIP-8 pixel in screenshots.
+    pub screenshot_scale: u32,
+    /// Records every presented frame while set.
+    pub gif: Option<GifRecorder>,
+    /// The last presented frame, to only redraw what changed.
+    presented: Option<Framebuffer>,
+    /// The message shown below the screen and when to clear it.
+    status: Option<(String, Instant)>,
+    /// Whether the status line has to be redrawn.
+    status_changed: bool,
 }
 
 impl Chip8Display for CrossTermDisplay {
@@ -32,102 +86,245 @@ impl Chip8Display for CrossTermDisplay {
 
         Self {
             stdout,
-            display: [false; 64 * 32],
+            colors: PLANE_COLORS,
+            screenshot_scale: 8,
+            gif: None,
+            presented: None,
+            status: None,
+            status_changed: false,
         }
     }
 
-    fn clear(&mut self) -> io::Result<()> {
-        self.display = [false; 64 * 32];
+    /// Draws every two rows of pixels as one row of half-block characters.
+    fn present(&mut self, framebuffer: &Framebuffer) -> io::Result<()> {
+        if let Some(gif) = &mut self.gif {
+            gif.push(framebuffer)?;
+        }
+        let previous = match self.presented.take() {
+            Some(previous) if previous.width() == framebuffer.width() => Some(previous),
+            _ => {
+                queue!(self.stdout, terminal::Clear(terminal::ClearType::All))?;
+                self.status_changed = true;
+                None
+            }
+        };
+        for hrow in 0..framebuffer.height() / 2 {
+            for col in 0..framebuffer.width() {
+                let top = framebuffer.pixel(col, 2 * hrow);
+                let bottom = framebuffer.pixel(col, 2 * hrow + 1);
+                if let Some(previous) = &previous {
+                    if previous.pixel(col, 2 * hrow) == top
+                        && previous.pixel(col, 2 * hrow + 1) == bottom
+                    {
+                        continue;
+                    }
+                }
+                let (top_color, bottom_color) =
+                    (self.colors[top as usize], self.colors[bottom as usize]);
+                let content = match (top, bottom) {
+                    (0, 0) => " ".stylize(),
+                    (0, _) => "▄".with(bottom_color),
+                    (_, 0) => "▀".with(top_color),
+                    _ => "▀".with(top_color).on(bottom_color),
+                };
+                queue!(
+                    self.stdout,
+                    cursor::MoveTo(col as u16, hrow as u16),
+                    style::PrintStyledContent(content)
+                )?;
+            }
+        }
+        self.present_status(framebuffer)?;
+        self.presented = Some(framebuffer.clone());
+        self.stdout.flush()
+    }
+}
+
+impl CrossTermDisplay {
+    /// Shows `message` below the screen for a few seconds.
+    pub fn show_status(&mut self, message: String) {
+        self.status = Some((message, Instant::now() + STATUS_TIME));
+        self.status_changed = true;
+    }
+
+    /// Draws the status line below the screen, clearing it once its time is up.
+    fn present_status(&mut self, framebuffer: &Framebuffer) -> io::Result<()> {
+        if matches!(&self.status, Some((_, until)) if Instant::now() >= *until) {
+            self.status = None;
+            self.status_changed = true;
+        }
+        if !self.status_changed {
+            return Ok(());
+        }
+        self.status_changed = false;
+        let width = framebuffer.width();
+        let message = self.status.as_ref().map_or("", |(message, _)| message);
+        let line: String = format!("{message:<width$}").chars().take(width).collect();
         queue!(
             self.stdout,
-            terminal::Clear(terminal::ClearType::All),
-            cursor::MoveTo(0, 0)
+            cursor::MoveTo(0, (framebuffer.height() / 2) as u16),
+            style::Print(line)
         )
     }
 
-    fn draw(&mut self, x: u8, y: u8, data: &[u8]) -> io::Result<bool> {
-        let mut pixel_cleared = false;
-        for (i, to_draw) in data.iter().enumerate() {
-            let row = y as usize + i;
-            for j in 0..8 {
-                let col = x + j;
-                let flip = to_draw & (1 << (7 - j)) > 0;
-
-                let display_index = row * 64 + col as usize;
-                if display_index >= self.display.len() {
-                    break;
-                }
-                if self.display[display_index] && flip {
-                    pixel_cleared = true;
-                }
-                self.display[display_index] ^= flip;
+    /// Starts recording a GIF to `path` in the display's colours, or finishes the recording in
+    /// progress. Returns whether a recording was started.
+    pub fn toggle_gif(&mut self, path: &Path) -> io::Result<bool> {
+        match self.gif.take() {
+            Some(gif) => {
+                gif.finish()?;
+                Ok(false)
+            }
+            None => {
+                self.gif = Some(GifRecorder::new(path, &self.colors, self.screenshot_scale)?);
+                Ok(true)
             }
         }
-        for hrow in 0..16 {
-            for hcol in 0..32 {
-                let mut block_index: u8 = 0;
-
-                for i in 0..=1 {
-                    for j in 0..=1 {
-                        let display_index = ((2 * hrow + i) * 64 + (2 * hcol + j)) as usize;
-                        if self.display[display_index] {
-                            block_index ^= 1 << (i * 2 + j);
-                        }
-                    }
-                }
-                assert!(block_index < 16, "{block_index:04b}");
+    }
 
-                const BLOCK_CHARACTERS: [&str; 16] = [
-                    "  ", "▀ ", " ▀", "▀▀", "▄ ", "█ ", "▄▀", "█▀", " ▄", "▀▄", " █", "▀█", "▄▄",
-                    "█▄", "▄█", "██",
-                ];
-                let block_character = BLOCK_CHARACTERS[block_index as usize];
-                queue!(
-                    self.stdout,
-                    cursor::MoveTo(hcol as u16 * 2, hrow as u16),
-                    style::PrintStyledContent(block_character.yellow())
-                )?;
+    /// Saves the last presented frame as a PNG in the display's colours, scaled up by
+    /// [`Self::screenshot_scale`]. Returns whether there was a frame to save.
+    pub fn save_png(&self, path: &Path) -> io::Result<bool> {
+        match &self.presented {
+            Some(framebuffer) => {
+                screenshot::save_png(framebuffer, &self.colors, self.screenshot_scale, path)?;
+                Ok(true)
             }
+            None => Ok(false),
         }
-        Ok(pixel_cleared)
     }
-    fn flush(&mut self) -> io::Result<()> {
-        self.stdout.flush()
+}
+
+/// Puts the terminal in raw mode, and asks it to report key releases if it supports that.
+/// Restores the terminal when dropped.
+struct TerminalSession {
+    reports_releases: bool,
+}
+
+impl TerminalSession {
+    fn start() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let reports_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases {
+            execute!(
+                stdout(),
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                )
+            )?;
+        }
+        Ok(Self { reports_releases })
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        if self.reports_releases {
+            let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout(), cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The key pressed in `event`. Ctrl+C is turned into Esc, since it doesn't interrupt crab8 in
+/// raw mode.
+fn key_code(event: &KeyEvent) -> KeyCode {
+    if event.code == KeyCode::Char('c') && event.modifiers.contains(KeyModifiers::CONTROL) {
+        KeyCode::Esc
+    } else {
+        event.code
     }
 }
 
 pub struct CrossTermKeyboard {
+    pub keymap: Keymap,
+    /// Whether the terminal reports key releases. If it doesn't, keys are released once the
+    /// terminal stops repeating them, or after [`Self::hold_window`] if it never did.
+    pub reports_releases: bool,
+    pub hold_window: Duration,
     key_states: u16,
+    /// When every key that is down will be released, if the terminal doesn't report releases.
+    release_deadlines: [Option<Instant>; 16],
+    /// When every key was last pressed or repeated.
+    last_presses: [Option<Instant>; 16],
+    /// The shortest time seen between two presses of a key that was down, which is how fast the
+    /// terminal repeats keys.
+    repeat_interval: Option<Duration>,
     last_key_pressed: Option<u8>,
+    /// Presses of keys that aren't on the CHIP-8 keypad, for the debugger.
+    hotkeys: Vec<KeyCode>,
 }
 
-fn crossterm_keymap(keycode: KeyCode) -> Option<u8> {
-    match keycode {
-        KeyCode::Char('1') => Some(0x1),
-        KeyCode::Char('2') => Some(0x2),
-        KeyCode::Char('3') => Some(0x3),
-        KeyCode::Char('4') => Some(0xC),
-        KeyCode::Char('q') => Some(0x4),
-        KeyCode::Char('w') => Some(0x5),
-        KeyCode::Char('e') => Some(0x6),
-        KeyCode::Char('r') => Some(0xD),
-        KeyCode::Char('a') => Some(0x7),
-        KeyCode::Char('s') => Some(0x8),
-        KeyCode::Char('d') => Some(0x9),
-        KeyCode::Char('f') => Some(0xE),
-        KeyCode::Char('z') => Some(0xA),
-        KeyCode::Char('x') => Some(0x0),
-        KeyCode::Char('c') => Some(0xB),
-        KeyCode::Char('v') => Some(0xF),
-        _ => None,
+impl CrossTermKeyboard {
+    /// Maximum number of unhandled hotkey presses kept around.
+    const MAX_HOTKEYS: usize = 16;
+    /// How long a key that the terminal repeats stays down after the last repeat, until the time
+    /// between repeats is known. This is longer than the time between repeats in common
+    /// terminals.
+    const REPEAT_HOLD: Duration = Duration::from_millis(150);
+    /// The shortest time a repeated key stays down, so repeats that are read in a burst don't
+    /// make the keys flicker.
+    const MIN_REPEAT_HOLD: Duration = Duration::from_millis(50);
+
+    fn press(&mut self, key: u8) {
+        let now = Instant::now();
+        let mut hold = self.hold_window;
+        if self.key_states & 1 << key == 0 {
+            self.last_key_pressed = Some(key);
+        } else {
+            if let Some(previous) = self.last_presses[key as usize] {
+                let interval = now - previous;
+                self.repeat_interval = Some(
+                    self.repeat_interval
+                        .map_or(interval, |repeat_interval| repeat_interval.min(interval)),
+                );
+            }
+            // Twice the repeat interval, so a single late repeat doesn't release the key.
+            let repeat_hold = self
+                .repeat_interval
+                .map_or(Self::REPEAT_HOLD, |interval| 2 * interval)
+                .max(Self::MIN_REPEAT_HOLD);
+            hold = hold.min(repeat_hold);
+        }
+        self.key_states |= 1 << key;
+        self.last_presses[key as usize] = Some(now);
+        self.release_deadlines[key as usize] = Some(now + hold);
+    }
+
+    /// Releases the keys whose hold window ran out, if the terminal doesn't report releases.
+    fn release_expired_keys(&mut self) {
+        if self.reports_releases {
+            return;
+        }
+        let now = Instant::now();
+        for key in 0..16 {
+            if self.release_deadlines[key].is_none_or(|deadline| deadline <= now) {
+                self.key_states &= !(1 << key);
+                self.release_deadlines[key] = None;
+            }
+        }
+    }
+
+    /// Takes the keys pressed since the last call that aren't on the CHIP-8 keypad.
+    fn take_hotkeys(&mut self) -> Vec<KeyCode> {
+        std::mem::take(&mut self.hotkeys)
     }
 }
 
 impl Chip8Keyboard for CrossTermKeyboard {
     fn new() -> Self {
         Self {
+            keymap: Keymap::default(),
+            reports_releases: false,
+            hold_window: Duration::from_millis(500),
             key_states: 0,
+            release_deadlines: [None; 16],
+            last_presses: [None; 16],
+            repeat_interval: None,
             last_key_pressed: None,
+            hotkeys: Vec::new(),
         }
     }
 
@@ -142,47 +339,66 @@ impl Chip8Keyboard for CrossTermKeyboard {
             }
             let duration = Duration::from_micros(leftover_time);
             if event::poll(duration)? {
-                if let Event::Key(KeyEvent { code, kind, .. }) = event::read()? {
-                    if let Some(key) = crossterm_keymap(code) {
-                        match kind {
-                            KeyEventKind::Press => {
-                                if self.key_states & 1 << key == 0 {
-                                    self.last_key_pressed = Some(key);
-                                }
-                                self.key_states |= 1 << key;
-                            }
+                if let Event::Key(event) = event::read()? {
+                    let code = key_code(&event);
+                    if let Some(key) = self.keymap.hex_key(code) {
+                        match event.kind {
+                            KeyEventKind::Press => self.press(key),
                             KeyEventKind::Release => self.key_states &= !(1 << key),
                             KeyEventKind::Repeat => {}
                         }
+                    } else if event.kind != KeyEventKind::Release
+                        && self.hotkeys.len() < Self::MAX_HOTKEYS
+                    {
+                        self.hotkeys.push(code);
                     }
                 }
             };
         }
+        self.release_expired_keys();
         Ok(())
     }
 
     fn is_key_down(&self, key: u8) -> bool {
-        self.key_states & (1 << key) > 0
+        key < 16 && self.key_states & (1 << key) != 0
     }
 
     fn last_key_pressed(&self) -> Option<u8> {
         self.last_key_pressed
     }
+
+    fn key_states(&self) -> u16 {
+        self.key_states
+    }
+
+    fn set_key_states(&mut self, key_states: u16) {
+        self.key_states = key_states;
+    }
 }
 
-fn rom_selector<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
+/// Lets the user pick a ROM in the directory at `path`, showing what `database` knows about
+/// the ROMs.
+fn rom_selector<P: AsRef<Path>>(path: P, database: &RomDatabase) -> io::Result<PathBuf> {
     let rom_paths = fs::read_dir(path)?;
 
     let paths: Vec<_> = rom_paths
         .map(|x| x.unwrap().path())
         .filter(|x| {
             if let Some(extension) = x.extension() {
-                extension == "ch8"
+                ["ch8", "sc8", "xo8"].iter().any(|rom| extension == *rom)
             } else {
                 false
             }
         })
         .collect();
+    let infos: Vec<_> = paths
+        .iter()
+        .map(|path| {
+            fs::read(path)
+                .ok()
+                .and_then(|program| database.lookup(&program))
+        })
+        .collect();
 
     let mut stdout = stdout();
     execute!(
@@ -213,7 +429,13 @@ fn rom_selector<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
                     break;
                 }
                 let filename = paths[index].file_name().unwrap().to_str().unwrap();
-                let line = format!("> {filename}");
+                let mut line = format!("> {filename}");
+                if let Some(info) = infos[index] {
+                    line += &format!("  {}", info.display_name());
+                    if index == selected_index && !info.keys.is_empty() {
+                        line += &format!("  [{}]", info.key_description());
+                    }
+                }
                 let mut content = line.white();
                 if scroll_value + i == selected_index {
                     content = content.black().on_white();
@@ -231,13 +453,14 @@ fn rom_selector<P: AsRef<Path>>(path: P) -> io::Result<PathBuf> {
 
         if event::poll(Duration::from_secs(1))? {
             needs_redraw = true;
-            if let Event::Key(KeyEvent {
-                code,
-                kind: KeyEventKind::Press | KeyEventKind::Repeat,
-                ..
-            }) = event::read()?
+            if let Event::Key(
+                event @ KeyEvent {
+                    kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                    ..
+                },
+            ) = event::read()?
             {
-                match code {
+                match key_code(&event) {
                     KeyCode::Char('w') | KeyCode::Up => {
                         if scroll_value == 0 && selected_index == 0 {
                             scroll_value = (paths.len() as i32 - rows as i32 + 2).max(0) as usize;
@@ -279,6 +502,7 @@ use cpal::SampleFormat;
 
 pub struct CpalBeeper {
     stream: Stream,
+    tone: Arc<Mutex<Tone>>,
 }
 
 impl Chip8Beeper for CpalBeeper {
@@ -298,25 +522,18 @@ impl Chip8Beeper for CpalBeeper {
         let sample_format = supported_config.sample_format();
         let config: StreamConfig = supported_config.into();
 
-        const FREQ: u32 = 440;
-
-        let num_samples_per_second = config.sample_rate.0;
-        let num_samples_per_repetition = num_samples_per_second / FREQ;
+        let tone = Arc::new(Mutex::new(Tone::new(volume)));
 
         fn create_stream<T: SizedSample + FromSample<f32>>(
             device: &Device,
             config: &StreamConfig,
-            volume: f32,
-            num_samples_per_repetition: u32,
+            tone: Arc<Mutex<Tone>>,
         ) -> Result<Stream, BuildStreamError> {
-            let mut index = 0;
-            let float_samples: Vec<_> = (0..num_samples_per_repetition)
-                .map(|i| (i as f32 / num_samples_per_repetition as f32 * TAU).sin() * volume)
-                .collect();
+            let sample_rate = config.sample_rate.0;
             let callback = move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut tone = tone.lock().unwrap();
                 for sample in data {
-                    *sample = T::from_sample(float_samples[index as usize]);
-                    index = (index + 1) % num_samples_per_repetition;
+                    *sample = T::from_sample(tone.next_sample(sample_rate));
                 }
             };
             let err_fn = |err| eprintln!("an error occurred on the output audio stream: {}", err);
@@ -324,49 +541,452 @@ impl Chip8Beeper for CpalBeeper {
         }
 
         let stream = match sample_format {
-            SampleFormat::F32 => {
-                create_stream::<f32>(&device, &config, volume, num_samples_per_repetition)
-            }
-            SampleFormat::I16 => {
-                create_stream::<i16>(&device, &config, volume, num_samples_per_repetition)
-            }
-            SampleFormat::U16 => {
-                create_stream::<u16>(&device, &config, volume, num_samples_per_repetition)
-            }
-            SampleFormat::U8 => {
-                create_stream::<u8>(&device, &config, volume, num_samples_per_repetition)
-            }
+            SampleFormat::F32 => create_stream::<f32>(&device, &config, tone.clone()),
+            SampleFormat::I16 => create_stream::<i16>(&device, &config, tone.clone()),
+            SampleFormat::U16 => create_stream::<u16>(&device, &config, tone.clone()),
+            SampleFormat::U8 => create_stream::<u8>(&device, &config, tone.clone()),
             sample_format => panic!("Unsupported sample format '{sample_format}'"),
         }
         .unwrap();
+        // The stream keeps running so the tone can ramp up and down without clicks.
+        stream.play().unwrap();
 
-        Self { stream }
+        Self { stream, tone }
     }
 
     fn play(&mut self) {
-        self.stream.play().unwrap()
+        self.tone.lock().unwrap().play();
     }
 
     fn pause(&mut self) {
-        self.stream.pause().unwrap()
+        self.tone.lock().unwrap().pause();
+    }
+
+    fn set_pattern(&mut self, pattern: &[u8; 16], playback_rate: f32) {
+        self.tone
+            .lock()
+            .unwrap()
+            .set_pattern(pattern, playback_rate);
+    }
+
+    fn volume(&self) -> f32 {
+        self.tone.lock().unwrap().volume
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.tone.lock().unwrap().volume = volume;
+    }
+
+    fn set_frequency(&mut self, frequency: f32) {
+        self.tone.lock().unwrap().frequency = frequency;
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform) {
+        self.tone.lock().unwrap().waveform = waveform;
+    }
+
+    fn set_envelope(&mut self, attack: f32, release: f32) {
+        let mut tone = self.tone.lock().unwrap();
+        tone.attack = attack;
+        tone.release = release;
     }
 }
 
 impl Drop for CpalBeeper {
     fn drop(&mut self) {
-        self.pause();
+        self.stream.pause().unwrap()
+    }
+}
+
+fn show_fault_screen(error: &Chip8Error) -> io::Result<()> {
+    let mut stdout = stdout();
+    execute!(
+        stdout,
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0),
+        style::PrintStyledContent("The program crashed 💥".bold().red()),
+        cursor::MoveTo(0, 2),
+        style::Print(error),
+        cursor::MoveTo(0, 4),
+        style::Print("Press any key to exit."),
+        cursor::MoveTo(0, 5),
+        cursor::Show
+    )?;
+    loop {
+        if let Event::Key(KeyEvent {
+            kind: KeyEventKind::Press,
+            ..
+        }) = event::read()?
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Seconds of gameplay kept around for rewinding.
+const REWIND_HISTORY_SECONDS: usize = 60;
+/// Frames stepped back by every press of the rewind hotkey.
+const REWIND_FRAMES: usize = 60;
+/// Change in volume for every press of the volume hotkeys.
+const VOLUME_STEP: f32 = 0.05;
+
+/// Handles the hotkeys available while a ROM runs: `-` and `+` change the volume unless the
+/// keymap puts them on the keypad, F8 saves a screenshot next to the ROM, F12 starts or stops
+/// recording a GIF there, F7 rewinds a second and F2 to F4 manage save states. Returns a status
+/// message if `key` was one of them, which describes the error if the hotkey failed.
+///
+/// Rewinding and save states jump to another state, so they are ignored while recording or
+/// playing back a movie.
+fn handle_hotkey<R: Chip8Random>(
+    key: KeyCode,
+    interpreter: &mut Interpreter<R>,
+    save_slots: &mut SaveSlots,
+) -> Option<String> {
+    run_hotkey(key, interpreter, save_slots).unwrap_or_else(|error| Some(format!("Error: {error}")))
+}
+
+fn run_hotkey<R: Chip8Random>(
+    key: KeyCode,
+    interpreter: &mut Interpreter<R>,
+    save_slots: &mut SaveSlots,
+) -> Chip8Result<Option<String>> {
+    // The paused debugger passes on every key, including those on the keypad.
+    let on_keypad = interpreter.keyboard.inner.keymap.hex_key(key).is_some();
+    let volume_change = match key {
+        _ if on_keypad => None,
+        KeyCode::Char('-') => Some(-VOLUME_STEP),
+        KeyCode::Char('+' | '=') => Some(VOLUME_STEP),
+        _ => None,
+    };
+    if let Some(change) = volume_change {
+        let volume = (interpreter.beeper.volume() + change).clamp(0., 1.);
+        interpreter.beeper.set_volume(volume);
+        return Ok(Some(format!("Volume {:.0}%", volume * 100.)));
+    }
+    if key == KeyCode::F(8) {
+        let path = screenshot::next_path(save_slots.rom(), "png");
+        let message = if interpreter.display.save_png(&path)? {
+            format!("Saved {}", path.display())
+        } else {
+            "Nothing to take a screenshot of".to_string()
+        };
+        return Ok(Some(message));
+    }
+    if key == KeyCode::F(12) {
+        let path = screenshot::next_path(save_slots.rom(), "gif");
+        let message = if interpreter.display.toggle_gif(&path)? {
+            format!("Recording {}", path.display())
+        } else {
+            "Stopped recording".to_string()
+        };
+        return Ok(Some(message));
+    }
+    if interpreter.keyboard.movie().is_some() {
+        return Ok(None);
     }
+    if key == KeyCode::F(7) {
+        let message = if interpreter.rewind(REWIND_FRAMES)? {
+            "Rewound"
+        } else {
+            "Nothing to rewind"
+        };
+        return Ok(Some(message.to_string()));
+    }
+    save_slots.handle_key(key, interpreter)
 }
 
-fn main() -> io::Result<()> {
-    let path = rom_selector("./testroms")?;
+/// Runs the loaded ROM at `path` paced against wall-clock time, handling the hotkeys, until it
+/// exits, Esc is pressed or the movie being played back ends.
+fn run<R: Chip8Random>(interpreter: &mut Interpreter<R>, path: &Path) -> Chip8Result<()> {
+    let mut save_slots = SaveSlots::new(path);
+    let frame_time = Duration::from_secs_f64(1. / 60.);
+    let mut next_frame = interpreter.clock.now();
+    loop {
+        let time_left = interpreter.clock.time_until(next_frame);
+        next_frame += frame_time;
+        if interpreter.run_paced_frame(time_left)?.exited {
+            return Ok(());
+        }
+        for key in interpreter.keyboard.inner.take_hotkeys() {
+            if key == KeyCode::Esc {
+                return Ok(());
+            }
+            if let Some(message) = handle_hotkey(key, interpreter, &mut save_slots) {
+                interpreter.display.show_status(message);
+            }
+        }
+        if interpreter.keyboard.playback_finished() {
+            return Ok(());
+        }
+    }
+}
 
-    let display = CrossTermDisplay::new();
-    let keyboard = CrossTermKeyboard::new();
-    let beeper = CpalBeeper::new(0.1);
-    let interpreter = Chip8Interpreter::new(700, display, keyboard, beeper);
+/// Loads `program` into `interpreter`, starting the playback or recording of a movie if asked.
+fn start<D: Chip8Display, K: Chip8Keyboard, B: Chip8Beeper, R: Chip8Random, C: Chip8Clock>(
+    interpreter: &mut Chip8Interpreter<D, MovieKeyboard<K>, B, R, C>,
+    program: &[u8],
+    options: &RunOptions,
+) -> Chip8Result<()> {
+    if let Some(seed) = options.seed {
+        interpreter.seed_rng(seed);
+    }
+    interpreter.beeper.set_frequency(options.frequency);
+    interpreter.beeper.set_waveform(options.waveform);
+    interpreter.beeper.set_envelope(
+        options.attack as f32 / 1000.,
+        options.release as f32 / 1000.,
+    );
+    if let Some(path) = &options.play {
+        let movie = Movie::from_bytes(&fs::read(path)?)?;
+        interpreter.start_playback(program, movie)
+    } else if options.record.is_some() {
+        let seed = options.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64
+        });
+        interpreter.start_recording(program, seed)
+    } else {
+        interpreter.load_program(program)
+    }
+}
+
+/// Describes how the playback of a movie ended, as returned by
+/// [`Chip8Interpreter::playback_in_sync`].
+fn playback_result(in_sync: Option<bool>) -> &'static str {
+    match in_sync {
+        Some(true) => "Playback ended in sync",
+        Some(false) => "Playback desynced",
+        None => "Playback ended",
+    }
+}
+
+/// How to run a ROM, from the command line, the ROM database or the defaults, in that order.
+struct RomSettings {
+    quirks: Quirks,
+    clock_speed: u32,
+    colors: Palette,
+}
 
-    interpreter.run(path)?;
+impl RomSettings {
+    fn new(rom: &Path, program: &[u8], options: &RunOptions, database: &RomDatabase) -> Self {
+        let info = database.lookup(program);
+        Self {
+            quirks: options
+                .quirks
+                .map(QuirksProfile::quirks)
+                .or_else(|| info.and_then(|info| info.quirks))
+                .unwrap_or_else(|| QuirksProfile::for_rom(rom).quirks()),
+            clock_speed: options
+                .clock
+                .or_else(|| info.and_then(|info| info.clock_speed))
+                .unwrap_or(700),
+            colors: options
+                .colors
+                .or_else(|| info.and_then(|info| info.colors))
+                .unwrap_or(PLANE_COLORS),
+        }
+    }
+}
+
+/// The random number generator to run with: the one of the movie being played back, or the one
+/// given with `--rng`.
+fn random_kind(options: &RunOptions) -> Chip8Result<RandomKind> {
+    if let Some(path) = &options.play {
+        return Ok(Movie::from_bytes(&fs::read(path)?)?.rng);
+    }
+    Ok(match options.rng {
+        RngKind::Xorshift => RandomKind::XorShift,
+        RngKind::ProgramPage => RandomKind::ProgramPage,
+    })
+}
+
+/// Loads the database given with `--rom-db`, or the bundled one.
+fn load_database(options: &RunOptions) -> Chip8Result<RomDatabase> {
+    match &options.rom_db {
+        Some(path) => Ok(RomDatabase::from_json(&fs::read_to_string(path)?)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?),
+        None => Ok(RomDatabase::bundled()),
+    }
+}
+
+/// Runs `rom` without a terminal or audio device for `--frames` frames, or until the movie
+/// being played back ends, and then prints or saves the screen.
+fn run_headless<R: Chip8Random>(
+    rom: &Path,
+    options: &RunOptions,
+    database: &RomDatabase,
+) -> Chip8Result<()> {
+    let program = fs::read(rom)?;
+    let settings = RomSettings::new(rom, &program, options, database);
+    let mut interpreter: Chip8Interpreter<_, _, _, R, VirtualClock> = Chip8Interpreter::new(
+        settings.clock_speed,
+        settings.quirks,
+        HeadlessDisplay::new(),
+        MovieKeyboard::<ScriptedKeyboard>::new(),
+        WavRecorder::<NullBeeper>::new(options.volume),
+    );
+    start(&mut interpreter, &program, options)?;
+    if options.wav.is_some() {
+        interpreter.beeper.start();
+    }
+    let mut frames = 0;
+    while options.frames.is_none_or(|limit| frames < limit)
+        && !interpreter.keyboard.playback_finished()
+    {
+        if interpreter.run_frame()?.exited {
+            break;
+        }
+        frames += 1;
+    }
+
+    match &options.screenshot {
+        Some(path) => screenshot::save_png(
+            &interpreter.framebuffer,
+            &settings.colors,
+            options.scale,
+            path,
+        )?,
+        None => print!("{}", interpreter.framebuffer),
+    }
+    if interpreter.keyboard.is_playing() {
+        eprintln!("{}", playback_result(interpreter.playback_in_sync()));
+    }
+    save_wav(&mut interpreter.beeper, options)
+}
+
+/// Writes the sound recorded by `beeper` to the `--wav` file, if one was given.
+fn save_wav<B: Chip8Beeper>(beeper: &mut WavRecorder<B>, options: &RunOptions) -> Chip8Result<()> {
+    if let (Some(path), Some(wav)) = (&options.wav, beeper.finish()) {
+        fs::write(path, wav)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run_cli(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_cli(Cli { command, options }: Cli) -> Chip8Result<()> {
+    let rom = match command {
+        Some(Command::Disasm { rom }) => {
+            let program = fs::read(rom)?;
+            print!("{}", Disassembly::new(&program));
+            return Ok(());
+        }
+        Some(Command::Asm { source, output }) => {
+            let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+            let assembly = assemble(&fs::read_to_string(source)?)
+                .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+            fs::write(&output, &assembly.program)?;
+            fs::write(output.with_extension("sym"), assembly.symbol_map())?;
+            return Ok(());
+        }
+        Some(Command::Run { rom }) => Some(rom),
+        None => None,
+    };
+
+    let database = load_database(&options)?;
+    if options.headless {
+        let Some(rom) = rom else {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "--headless needs a ROM, use `crab8 run <ROM> --headless`",
+                )
+                .exit();
+        };
+        if options.frames.is_none() && options.play.is_none() {
+            Cli::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "--headless needs --frames or --play to know when to stop",
+                )
+                .exit();
+        }
+        return match random_kind(&options)? {
+            RandomKind::XorShift => run_headless::<XorShiftRng>(&rom, &options, &database),
+            RandomKind::ProgramPage => run_headless::<ProgramPageRng>(&rom, &options, &database),
+        };
+    }
+
+    let session = TerminalSession::start()?;
+    let path = match rom {
+        Some(rom) => rom,
+        None => match rom_selector(&options.rom_dir, &database) {
+            Err(error) if error.kind() == ErrorKind::Interrupted => return Ok(()),
+            path => path?,
+        },
+    };
+
+    match random_kind(&options)? {
+        RandomKind::XorShift => run_interactive::<XorShiftRng>(&path, &options, &database, session),
+        RandomKind::ProgramPage => {
+            run_interactive::<ProgramPageRng>(&path, &options, &database, session)
+        }
+    }
+}
+
+/// Runs the ROM at `path` in the terminal, in the debugger if asked.
+fn run_interactive<R: Chip8Random>(
+    path: &Path,
+    options: &RunOptions,
+    database: &RomDatabase,
+    session: TerminalSession,
+) -> Chip8Result<()> {
+    let program = fs::read(path)?;
+    let settings = RomSettings::new(path, &program, options, database);
+    let mut display = CrossTermDisplay::new();
+    display.colors = settings.colors;
+    display.screenshot_scale = options.scale;
+    let mut keyboard = MovieKeyboard::<CrossTermKeyboard>::new();
+    keyboard.inner.keymap = options.keymap.for_rom(path);
+    keyboard.inner.reports_releases = session.reports_releases;
+    keyboard.inner.hold_window = Duration::from_millis(options.key_hold);
+    let beeper = WavRecorder::<CpalBeeper>::new(options.volume);
+    let mut interpreter: Interpreter<R> = Chip8Interpreter::new(
+        settings.clock_speed,
+        settings.quirks,
+        display,
+        keyboard,
+        beeper,
+    );
+    interpreter.set_rewind_capacity(REWIND_HISTORY_SECONDS * 60);
+    start(&mut interpreter, &program, options)?;
+    if options.wav.is_some() {
+        interpreter.beeper.start();
+    }
+
+    let result = if options.debug {
+        debugger::run(&mut interpreter, path)
+    } else {
+        run(&mut interpreter, path)
+    };
+    if let Some(gif) = interpreter.display.gif.take() {
+        gif.finish()?;
+    }
+    save_wav(&mut interpreter.beeper, options)?;
+    if let Err(error) = result {
+        show_fault_screen(&error)?;
+        return Err(error);
+    }
+    drop(session);
+
+    if let Some(record) = &options.record {
+        if let Some(movie) = interpreter.finish_recording() {
+            fs::write(record, movie.to_bytes())?;
+        }
+    } else if interpreter.keyboard.is_playing() {
+        execute!(stdout(), cursor::MoveTo(0, 0), cursor::Show)?;
+        println!("{}", playback_result(interpreter.playback_in_sync()));
+    }
 
     Ok(())
 }
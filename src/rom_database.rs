@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crab8_core::{Quirks, CHIP8_MEMORY_SIZE, MAX_CLOCK_SPEED, MIN_CLOCK_SPEED};
+use serde::Deserialize;
+
+use crate::{cli::parse_palette, Palette};
+
+/// The bundled database, in the `programs.json` format of the community CHIP-8 database at
+/// <https://github.com/chip-8/chip-8-database>.
+const BUNDLED_PROGRAMS: &str = include_str!("../data/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    /// Platforms the ROM runs on, best one first.
+    #[serde(default)]
+    platforms: Vec<String>,
+    /// Quirks that differ from the platform's defaults, by platform.
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    /// Instructions per frame.
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    /// Hex keys by what they do in the game.
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QuirkOverrides {
+    /// Shifts work on Vx in place.
+    shift: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    /// `FX55`/`FX65` add X to I instead of X + 1.
+    memory_increment_by_x: Option<bool>,
+    /// Sprites wrap around the edges of the screen.
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    /// Colours for pixels that are off and on, then for the other XO-CHIP planes.
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+/// What the database knows about a ROM.
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub quirks: Option<Quirks>,
+    pub clock_speed: Option<u32>,
+    pub colors: Option<Palette>,
+    /// Hex keys by what they do in the game, like `up` or `a`.
+    pub keys: BTreeMap<String, u8>,
+}
+
+impl RomInfo {
+    /// The title, followed by the authors if they're known.
+    pub fn display_name(&self) -> String {
+        if self.authors.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} by {}", self.title, self.authors.join(", "))
+        }
+    }
+
+    /// Describes the keys, like `up: 5, down: 8`.
+    pub fn key_description(&self) -> String {
+        self.keys
+            .iter()
+            .map(|(action, key)| format!("{action}: {key:X}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// The quirks of a platform in the database, or `None` if crab8 can't emulate it.
+fn platform_quirks(platform: &str) -> Option<Quirks> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(Quirks::COSMAC_VIP),
+        "modernChip8" => Some(Quirks {
+            shift_uses_vy: false,
+            load_store_increments_index: false,
+            index_skips_last_register: false,
+            logic_resets_flag: false,
+            jump_uses_vx: false,
+            display_wait: false,
+            index_overflow_sets_flag: false,
+            wrap_sprites: false,
+            memory_size: CHIP8_MEMORY_SIZE,
+        }),
+        "chip48" => Some(Quirks::CHIP_48),
+        "superchip1" | "superchip" => Some(Quirks::SCHIP_MODERN),
+        "xochip" => Some(Quirks::XO_CHIP),
+        _ => None,
+    }
+}
+
+impl Rom {
+    /// The clock speed for the tick rate, if the ROM has one that crab8 can run at.
+    fn clock_speed(&self) -> Option<u32> {
+        self.tickrate
+            .and_then(|tickrate| tickrate.checked_mul(60))
+            .filter(|clock_speed| (MIN_CLOCK_SPEED..=MAX_CLOCK_SPEED).contains(clock_speed))
+    }
+
+    /// The quirks of the first platform that can be emulated, with the ROM's overrides.
+    fn quirks(&self) -> Option<Quirks> {
+        self.platforms.iter().find_map(|platform| {
+            let mut quirks = platform_quirks(platform)?;
+            if let Some(overrides) = self.quirky_platforms.get(platform) {
+                if let Some(shift) = overrides.shift {
+                    quirks.shift_uses_vy = !shift;
+                }
+                if let Some(leave_unchanged) = overrides.memory_leave_i_unchanged {
+                    quirks.load_store_increments_index = !leave_unchanged;
+                }
+                if let Some(increment_by_x) = overrides.memory_increment_by_x {
+                    quirks.index_skips_last_register = increment_by_x;
+                }
+                if let Some(wrap) = overrides.wrap {
+                    quirks.wrap_sprites = wrap;
+                }
+                if let Some(jump) = overrides.jump {
+                    quirks.jump_uses_vx = jump;
+                }
+                if let Some(vblank) = overrides.vblank {
+                    quirks.display_wait = vblank;
+                }
+                if let Some(logic) = overrides.logic {
+                    quirks.logic_resets_flag = logic;
+                }
+            }
+            Some(quirks)
+        })
+    }
+}
+
+/// Settings for known ROMs, looked up by the SHA-1 hash of the ROM.
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    /// The database bundled with crab8.
+    pub fn bundled() -> Self {
+        Self::from_json(BUNDLED_PROGRAMS).expect("the bundled ROM database is invalid")
+    }
+
+    /// Reads a database in the `programs.json` format.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let programs: Vec<Program> = serde_json::from_str(json)?;
+        let mut roms = HashMap::new();
+        for program in programs {
+            for (hash, rom) in program.roms {
+                let colors = rom.colors.as_ref().and_then(|colors| {
+                    let colors = colors.pixels.join(",");
+                    parse_palette(&colors).ok()
+                });
+                let info = RomInfo {
+                    title: program.title.clone(),
+                    authors: program.authors.clone(),
+                    quirks: rom.quirks(),
+                    clock_speed: rom.clock_speed(),
+                    colors,
+                    keys: rom.keys,
+                };
+                roms.insert(hash.to_ascii_lowercase(), info);
+            }
+        }
+        Ok(Self { roms })
+    }
+
+    pub fn lookup(&self, program: &[u8]) -> Option<&RomInfo> {
+        let hash = sha1_smol::Sha1::from(program).digest().to_string();
+        self.roms.get(&hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::Color;
+
+    /// The IBM logo ROM.
+    const IBM_LOGO: &str = "00e0a22a600c6108d01f7009a239d01fa2487008d01f7004a257d01f7008a266d01f\
+        7008a275d01f1228ff00ff003c003c003c003c00ff00ffff00ff0038003f003f003800ff00ff8000e000e000\
+        80008000e000e00080f800fc003e003f003b003900f800f8030007000f00bf00fb00f300e30043e000e00080\
+        00800080008000e000e0";
+
+    fn ibm_logo() -> Vec<u8> {
+        (0..IBM_LOGO.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&IBM_LOGO[index..index + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bundled_database_knows_the_ibm_logo() {
+        let database = RomDatabase::bundled();
+        let info = database.lookup(&ibm_logo()).unwrap();
+        assert_eq!(info.title, "IBM Logo");
+        assert_eq!(info.quirks, Some(Quirks::COSMAC_VIP));
+        assert!(database.lookup(&[0x12, 0x00]).is_none());
+    }
+
+    #[test]
+    fn bundled_database_resolves_the_platform_of_maze() {
+        let maze = [
+            0xA2, 0x1E, 0xC2, 0x01, 0x32, 0x01, 0xA2, 0x1A, 0xD0, 0x14, 0x70, 0x04, 0x30, 0x40,
+            0x12, 0x00, 0x60, 0x00, 0x71, 0x04, 0x31, 0x20, 0x12, 0x00, 0x12, 0x18, 0x80, 0x40,
+            0x20, 0x10, 0x20, 0x40, 0x80, 0x10,
+        ];
+        let database = RomDatabase::bundled();
+        let info = database.lookup(&maze).unwrap();
+        assert_eq!(info.display_name(), "Maze by David Winter");
+        assert_eq!(info.quirks, Some(Quirks::COSMAC_VIP));
+    }
+
+    #[test]
+    fn settings_are_read_from_the_entry() {
+        let json = r##"[{
+            "title": "Test",
+            "authors": ["Someone"],
+            "roms": {
+                "1BA58656810B67FD131EB9AF3E3987863BF26C90": {
+                    "platforms": ["unknown", "superchip"],
+                    "quirkyPlatforms": { "superchip": { "shift": false, "vblank": true } },
+                    "tickrate": 30,
+                    "colors": { "pixels": ["#000000", "#FFFFFF"] },
+                    "keys": { "up": 5 }
+                }
+            }
+        }]"##;
+        let database = RomDatabase::from_json(json).unwrap();
+        let info = database.lookup(&ibm_logo()).unwrap();
+        assert_eq!(info.display_name(), "Test by Someone");
+        let quirks = info.quirks.unwrap();
+        assert!(quirks.shift_uses_vy);
+        assert!(quirks.display_wait);
+        assert!(quirks.jump_uses_vx);
+        assert_eq!(info.clock_speed, Some(1800));
+        assert_eq!(
+            info.colors.unwrap()[1],
+            Color::Rgb {
+                r: 255,
+                g: 255,
+                b: 255
+            }
+        );
+        assert_eq!(info.key_description(), "up: 5");
+    }
+
+    #[test]
+    fn unusable_tick_rates_are_ignored() {
+        for tickrate in [0, 2_000_000, u32::MAX] {
+            let json = format!(
+                r#"[{{"title": "Test", "roms": {{"{}": {{"tickrate": {tickrate}}}}}}}]"#,
+                sha1_smol::Sha1::from(ibm_logo()).digest()
+            );
+            let database = RomDatabase::from_json(&json).unwrap();
+            assert_eq!(database.lookup(&ibm_logo()).unwrap().clock_speed, None);
+        }
+    }
+}
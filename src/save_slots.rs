@@ -0,0 +1,75 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crab8_core::{Chip8Random, Chip8Result, Snapshot};
+use crossterm::event::KeyCode;
+
+use crate::Interpreter;
+
+/// Number of save state slots per ROM.
+const SLOT_COUNT: u8 = 9;
+
+/// Numbered save states for a ROM, stored next to it as `<rom>.<slot>.state`.
+pub struct SaveSlots {
+    rom: PathBuf,
+    slot: u8,
+}
+
+impl SaveSlots {
+    pub fn new(rom: &Path) -> Self {
+        Self {
+            rom: rom.to_path_buf(),
+            slot: 1,
+        }
+    }
+
+    /// The ROM the save states belong to.
+    pub fn rom(&self) -> &Path {
+        &self.rom
+    }
+
+    fn path(&self) -> PathBuf {
+        let mut path = OsString::from(self.rom.as_os_str());
+        path.push(format!(".{}.state", self.slot));
+        path.into()
+    }
+
+    /// Handles the save state hotkeys: F2 saves to the current slot, F3 loads it and F4 moves
+    /// to the next slot. Returns a status message if `key` was one of them.
+    pub fn handle_key<R: Chip8Random>(
+        &mut self,
+        key: KeyCode,
+        interpreter: &mut Interpreter<R>,
+    ) -> Chip8Result<Option<String>> {
+        let slot = self.slot;
+        let message = match key {
+            KeyCode::F(2) => {
+                fs::write(self.path(), interpreter.snapshot().to_bytes())?;
+                format!("Saved slot {slot}")
+            }
+            KeyCode::F(3) => match fs::read(self.path()) {
+                Ok(bytes) => match Snapshot::from_bytes(&bytes) {
+                    Ok(snapshot) => {
+                        interpreter.restore(&snapshot)?;
+                        format!("Loaded slot {slot}")
+                    }
+                    Err(error) => format!("Could not load slot {slot}: {error}"),
+                },
+                Err(error) if error.kind() == ErrorKind::NotFound => {
+                    format!("Slot {slot} is empty")
+                }
+                Err(error) => return Err(error.into()),
+            },
+            KeyCode::F(4) => {
+                self.slot = self.slot % SLOT_COUNT + 1;
+                format!("Selected slot {}", self.slot)
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(message))
+    }
+}
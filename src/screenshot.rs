@@ -0,0 +1,110 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crab8_core::Framebuffer;
+use crossterm::style::Color;
+
+use crate::Palette;
+
+/// The first 16 colours of the 256 colour palette.
+const ANSI_COLORS: [Color; 16] = [
+    Color::Black,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+    Color::Grey,
+    Color::DarkGrey,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+/// The RGB value of a terminal colour, using the usual xterm colours for the named ones. The
+/// terminal's default colour is taken to be black.
+pub fn rgb(color: Color) -> [u8; 3] {
+    match color {
+        Color::Rgb { r, g, b } => [r, g, b],
+        Color::Reset | Color::Black => [0x00, 0x00, 0x00],
+        Color::DarkGrey => [0x80, 0x80, 0x80],
+        Color::Red => [0xFF, 0x00, 0x00],
+        Color::DarkRed => [0x80, 0x00, 0x00],
+        Color::Green => [0x00, 0xFF, 0x00],
+        Color::DarkGreen => [0x00, 0x80, 0x00],
+        Color::Yellow => [0xFF, 0xFF, 0x00],
+        Color::DarkYellow => [0x80, 0x80, 0x00],
+        Color::Blue => [0x00, 0x00, 0xFF],
+        Color::DarkBlue => [0x00, 0x00, 0x80],
+        Color::Magenta => [0xFF, 0x00, 0xFF],
+        Color::DarkMagenta => [0x80, 0x00, 0x80],
+        Color::Cyan => [0x00, 0xFF, 0xFF],
+        Color::DarkCyan => [0x00, 0x80, 0x80],
+        Color::White => [0xFF, 0xFF, 0xFF],
+        Color::Grey => [0xC0, 0xC0, 0xC0],
+        Color::AnsiValue(value) => match value {
+            0..=15 => rgb(ANSI_COLORS[value as usize]),
+            16..=231 => {
+                let level = |index: u8| if index == 0 { 0 } else { 55 + index * 40 };
+                let index = value - 16;
+                [level(index / 36), level(index / 6 % 6), level(index % 6)]
+            }
+            _ => [8 + (value - 232) * 10; 3],
+        },
+    }
+}
+
+/// Saves the screen as a PNG, drawing every CHIP-8 pixel as a square of `scale` by `scale`
+/// image pixels.
+pub fn save_png(
+    framebuffer: &Framebuffer,
+    palette: &Palette,
+    scale: u32,
+    path: &Path,
+) -> io::Result<()> {
+    let scale = scale.max(1) as usize;
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        (width * scale) as u32,
+        (height * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut data = Vec::with_capacity(width * height * scale * scale * 3);
+    for row in framebuffer.pixels().chunks(width) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|pixel| rgb(palette[*pixel as usize & 0b11]).repeat(scale))
+            .collect();
+        for _ in 0..scale {
+            data.extend_from_slice(&line);
+        }
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(io::Error::other)
+}
+
+/// The first free path of the form `<rom>.<number>.<extension>`, for a new screenshot or
+/// recording of `rom`.
+pub fn next_path(rom: &Path, extension: &str) -> PathBuf {
+    (1..)
+        .map(|number| {
+            let mut path = OsString::from(rom.as_os_str());
+            path.push(format!(".{number}.{extension}"));
+            PathBuf::from(path)
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}